@@ -0,0 +1,45 @@
+#[cfg(target_os = "linux")]
+pub mod evdev;
+pub mod rdev;
+pub mod script;
+
+use std::sync::Arc;
+
+use crate::{
+    input::{Input, InputEvent},
+    settings::{Backend, InputSettings},
+};
+
+pub type EventSink = Box<dyn FnMut(InputEvent) + Send>;
+
+pub trait InputSource: Send {
+    // blocks until the source runs out of input or fails, handing every event to the sink
+    fn listen(self: Box<Self>, sink: EventSink) -> anyhow::Result<()>;
+
+    // taken before listening, for backends that can read the actual state of a key
+    fn probe(&self) -> Option<Arc<dyn KeyProbe>> {
+        None
+    }
+}
+
+pub trait KeyProbe: Send + Sync {
+    // None when the state can't be read
+    fn is_held(&self, input: &Input, scancode: Option<u32>) -> Option<bool>;
+}
+
+pub fn from_settings(settings: &InputSettings) -> anyhow::Result<Box<dyn InputSource>> {
+    match settings.backend {
+        Backend::Rdev => Ok(Box::new(self::rdev::RdevSource)),
+        Backend::Script => Ok(Box::new(self::script::ScriptSource::load(
+            &settings.script_path,
+        )?)),
+
+        #[cfg(target_os = "linux")]
+        Backend::Evdev => Ok(Box::new(self::evdev::EvdevSource::new(
+            settings.evdev_devices.clone(),
+        ))),
+
+        #[cfg(not(target_os = "linux"))]
+        Backend::Evdev => anyhow::bail!("The evdev input backend is only available on linux"),
+    }
+}
@@ -0,0 +1,485 @@
+extern crate libc;
+
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::Read;
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, UNIX_EPOCH};
+
+use crate::{
+    helper,
+    input::{Device, Input, InputEvent, MouseButton, Timestamp},
+    key::Key,
+};
+
+use super::{EventSink, InputSource, KeyProbe};
+
+const INPUT_DIR: &str = "/dev/input";
+const BY_ID_DIR: &str = "/dev/input/by-id";
+const SYS_INPUT_DIR: &str = "/sys/class/input";
+
+// new devices are picked up this often, unplugged ones drop out when their read fails
+const RESCAN_INTERVAL: Duration = Duration::from_secs(2);
+
+// from linux/input-event-codes.h
+const EV_KEY: u16 = 0x01;
+const EV_REL: u16 = 0x02;
+const REL_HWHEEL: u16 = 0x06;
+const REL_WHEEL: u16 = 0x08;
+const BTN_MISC: u16 = 0x100;
+const BTN_LEFT: u16 = 0x110;
+const BTN_RIGHT: u16 = 0x111;
+const BTN_MIDDLE: u16 = 0x112;
+const BTN_SIDE: u16 = 0x113;
+const BTN_EXTRA: u16 = 0x114;
+const BTN_TASK: u16 = 0x117;
+const BTN_GEAR_UP: u16 = 0x151;
+const BTN_DPAD_UP: u16 = 0x220;
+const BTN_DPAD_RIGHT: u16 = 0x223;
+const BTN_TRIGGER_HAPPY: u16 = 0x2C0;
+const KEY_MAX: usize = 0x2FF;
+
+// EVIOCGKEY from linux/input.h, reads which keys are down as a bitmask
+const KEY_STATE_LEN: usize = KEY_MAX / 8 + 1;
+const EVIOCGKEY: libc::c_ulong =
+    (2 << 30) | ((KEY_STATE_LEN as libc::c_ulong) << 16) | (0x45 << 8) | 0x18;
+
+// reads /dev/input/event* directly, works without x11 but needs read access to the devices
+// (usually membership of the input group)
+pub struct EvdevSource {
+    // entries starting with '/' are paths, anything else matches device ids or names
+    devices: Vec<String>,
+    open: Arc<Mutex<HashSet<PathBuf>>>,
+}
+
+impl EvdevSource {
+    pub fn new(devices: Vec<String>) -> Self {
+        Self {
+            devices,
+            open: Arc::new(Mutex::new(HashSet::new())),
+        }
+    }
+
+    fn wants(&self, path: &Path, device: &Device) -> bool {
+        if self.devices.is_empty() {
+            return true;
+        }
+
+        self.devices.iter().any(|filter| {
+            if filter.starts_with('/') {
+                // by-id and by-path links resolve to the event node
+                std::fs::canonicalize(filter).is_ok_and(|filter| filter == path)
+            } else {
+                device.matches(filter)
+            }
+        })
+    }
+}
+
+fn device_name(node: &str) -> String {
+    let path = format!("{}/{}/device/name", SYS_INPUT_DIR, node);
+
+    match std::fs::read_to_string(path) {
+        Ok(name) => name.trim().to_string(),
+        Err(_) => String::new(),
+    }
+}
+
+// event numbers change on replug, so prefer the udev by-id link and fall back to the usb ids
+fn device_id(path: &Path, node: &str) -> String {
+    if let Ok(entries) = std::fs::read_dir(BY_ID_DIR) {
+        for entry in entries.flatten() {
+            if std::fs::canonicalize(entry.path()).is_ok_and(|target| target == path) {
+                return entry.file_name().to_string_lossy().to_string();
+            }
+        }
+    }
+
+    let read_id = |field: &str| {
+        let path = format!("{}/{}/device/id/{}", SYS_INPUT_DIR, node, field);
+        std::fs::read_to_string(path).map(|id| id.trim().to_string())
+    };
+
+    match (read_id("vendor"), read_id("product")) {
+        (Ok(vendor), Ok(product)) => format!("{}:{}", vendor, product),
+        _ => node.to_string(),
+    }
+}
+
+// only devices that report keys or buttons are worth reading
+fn has_keys(node: &str) -> bool {
+    let path = format!("{}/{}/device/capabilities/ev", SYS_INPUT_DIR, node);
+
+    let bits = match std::fs::read_to_string(path) {
+        Ok(bits) => bits,
+        Err(_) => return false,
+    };
+
+    let low_word = bits.split_whitespace().last().unwrap_or("0");
+
+    match u64::from_str_radix(low_word, 16) {
+        Ok(bits) => bits & (1 << EV_KEY) != 0,
+        Err(_) => false,
+    }
+}
+
+// keys the scancode table doesn't know keep their x11 keycode, same as rdev reports them
+fn evdev_to_key(code: u16) -> Key {
+    let code = code as u32;
+    helper::scancode_to_key(code).unwrap_or(Key::Unknown(code + 8))
+}
+
+// touchpads, tablets and gamepads report touches and tools as buttons, only mouse buttons
+// are kept from those. codes between the button ranges are keys, like KEY_FN
+fn is_key_or_mouse(code: u16) -> bool {
+    match code {
+        BTN_LEFT..=BTN_TASK => true,
+        BTN_MISC..=BTN_GEAR_UP | BTN_DPAD_UP..=BTN_DPAD_RIGHT | BTN_TRIGGER_HAPPY.. => false,
+        _ => true,
+    }
+}
+
+fn convert(event: &libc::input_event) -> Vec<InputEvent> {
+    let since_epoch = Duration::new(event.time.tv_sec as u64, event.time.tv_usec as u32 * 1000);
+    let time = Timestamp::new(UNIX_EPOCH + since_epoch);
+
+    match (event.type_, event.code) {
+        // 2 is autorepeat, held keys are already tracked
+        (EV_KEY, _) if event.value == 2 => vec![],
+
+        (EV_KEY, code) if !self::is_key_or_mouse(code) => vec![],
+
+        (EV_KEY, code) => {
+            let is_down = event.value == 1;
+
+            let button = match code {
+                BTN_LEFT => Some(MouseButton::Left),
+                BTN_RIGHT => Some(MouseButton::Right),
+                BTN_MIDDLE => Some(MouseButton::Middle),
+                BTN_SIDE => Some(MouseButton::Back),
+                BTN_EXTRA => Some(MouseButton::Forward),
+                _ => None,
+            };
+
+            match button {
+                Some(button) => vec![InputEvent::new(Input::Mouse(button), None, is_down, time)],
+                None => {
+                    let key = self::evdev_to_key(code);
+                    vec![InputEvent::new(
+                        Input::Key(key),
+                        Some(code as u32),
+                        is_down,
+                        time,
+                    )]
+                }
+            }
+        }
+
+        (EV_REL, REL_WHEEL | REL_HWHEEL) => {
+            let value = event.value as i64;
+
+            let (delta_x, delta_y) = if event.code == REL_WHEEL {
+                (0, value)
+            } else {
+                (value, 0)
+            };
+
+            helper::rdev_to_wheel(delta_x, delta_y)
+                .into_iter()
+                .flat_map(|direction| {
+                    let input = Input::Wheel(direction);
+
+                    [
+                        InputEvent::new(input.clone(), None, true, time),
+                        InputEvent::new(input, None, false, time),
+                    ]
+                })
+                .collect()
+        }
+
+        _ => vec![],
+    }
+}
+
+fn read_device(
+    mut file: File,
+    path: PathBuf,
+    device: Device,
+    open: Arc<Mutex<HashSet<PathBuf>>>,
+    sender: Sender<InputEvent>,
+) {
+    let mut buffer = [0_u8; std::mem::size_of::<libc::input_event>()];
+
+    // read fails with ENODEV once the device is unplugged
+    while file.read_exact(&mut buffer).is_ok() {
+        // SAFETY: the kernel hands out whole input_event structs and any bit pattern is valid
+        let event =
+            unsafe { std::ptr::read_unaligned(buffer.as_ptr() as *const libc::input_event) };
+
+        for input_event in self::convert(&event) {
+            let input_event = input_event.with_device(Some(device.clone()));
+
+            if sender.send(input_event).is_err() {
+                return;
+            }
+        }
+    }
+
+    open.lock().unwrap().remove(&path);
+}
+
+impl EvdevSource {
+    // opens every wanted device that isn't open yet, returns the errors of those that failed
+    fn scan(&self, sender: &Sender<InputEvent>) -> Vec<String> {
+        let open = &self.open;
+        let mut errors = vec![];
+
+        let entries = match std::fs::read_dir(INPUT_DIR) {
+            Ok(entries) => entries,
+            Err(err) => return vec![format!("{}: {}", INPUT_DIR, err)],
+        };
+
+        for entry in entries.flatten() {
+            let node = entry.file_name().to_string_lossy().to_string();
+
+            if !node.starts_with("event") || !self::has_keys(&node) {
+                continue;
+            }
+
+            let path = entry.path();
+
+            if open.lock().unwrap().contains(&path) {
+                continue;
+            }
+
+            let device = Device::new(self::device_id(&path, &node), self::device_name(&node));
+
+            if !self.wants(&path, &device) {
+                continue;
+            }
+
+            let file = match File::open(&path) {
+                Ok(file) => file,
+                Err(err) => {
+                    errors.push(format!("{} ({}): {}", path.display(), device.name, err));
+                    continue;
+                }
+            };
+
+            open.lock().unwrap().insert(path.clone());
+
+            let open = open.clone();
+            let sender = sender.clone();
+            thread::spawn(move || self::read_device(file, path, device, open, sender));
+        }
+
+        errors
+    }
+}
+
+impl InputSource for EvdevSource {
+    fn probe(&self) -> Option<Arc<dyn KeyProbe>> {
+        Some(Arc::new(EvdevProbe {
+            open: self.open.clone(),
+        }))
+    }
+
+    fn listen(self: Box<Self>, mut sink: EventSink) -> anyhow::Result<()> {
+        let (sender, receiver) = mpsc::channel();
+
+        let errors = self.scan(&sender);
+
+        // devices that aren't plugged in yet are fine, ones we can't read are not
+        if self.open.lock().unwrap().is_empty() && !errors.is_empty() {
+            anyhow::bail!("Failed to open any input device:\n{}", errors.join("\n"));
+        }
+
+        thread::spawn(move || loop {
+            thread::sleep(RESCAN_INTERVAL);
+            let _ = self.scan(&sender);
+        });
+
+        for event in receiver {
+            sink(event);
+        }
+
+        Ok(())
+    }
+}
+
+// asks every open device whether a key is down, on its own handle so reads aren't disturbed
+pub struct EvdevProbe {
+    open: Arc<Mutex<HashSet<PathBuf>>>,
+}
+
+fn key_state(path: &Path) -> Option<[u8; KEY_STATE_LEN]> {
+    let file = File::open(path).ok()?;
+    let mut state = [0_u8; KEY_STATE_LEN];
+
+    // SAFETY: the buffer is exactly the length encoded in the request
+    let result = unsafe { libc::ioctl(file.as_raw_fd(), EVIOCGKEY as _, state.as_mut_ptr()) };
+
+    (result >= 0).then_some(state)
+}
+
+fn button_code(button: &MouseButton) -> u16 {
+    match button {
+        MouseButton::Left => BTN_LEFT,
+        MouseButton::Right => BTN_RIGHT,
+        MouseButton::Middle => BTN_MIDDLE,
+        MouseButton::Back => BTN_SIDE,
+        MouseButton::Forward => BTN_EXTRA,
+    }
+}
+
+impl KeyProbe for EvdevProbe {
+    fn is_held(&self, input: &Input, scancode: Option<u32>) -> Option<bool> {
+        let code = match input {
+            Input::Key(_) => scancode? as usize,
+            Input::Mouse(button) => self::button_code(button) as usize,
+
+            // pulsed, never held
+            Input::Wheel(_) => return Some(false),
+        };
+
+        if code > KEY_MAX {
+            return None;
+        }
+
+        let paths: Vec<PathBuf> = self.open.lock().unwrap().iter().cloned().collect();
+        let mut answered = false;
+
+        for path in paths {
+            let state = match self::key_state(&path) {
+                Some(state) => state,
+                None => continue,
+            };
+
+            if state[code / 8] & (1 << (code % 8)) != 0 {
+                return Some(true);
+            }
+
+            answered = true;
+        }
+
+        answered.then_some(false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::WheelDirection;
+
+    const BTN_TOUCH: u16 = 0x14A;
+    const BTN_TOOL_FINGER: u16 = 0x145;
+    const KEY_FN: u16 = 0x1D0;
+
+    fn event(type_: u16, code: u16, value: i32) -> libc::input_event {
+        libc::input_event {
+            time: libc::timeval {
+                tv_sec: 1_700_000_000,
+                tv_usec: 250_000,
+            },
+            type_,
+            code,
+            value,
+        }
+    }
+
+    fn inputs(event: &libc::input_event) -> Vec<(Input, Option<u32>, bool)> {
+        self::convert(event)
+            .into_iter()
+            .map(|e| (e.input, e.scancode, e.is_down))
+            .collect()
+    }
+
+    #[test]
+    fn keys_keep_their_code() {
+        assert_eq!(
+            inputs(&event(EV_KEY, 44, 1)),
+            vec![(Input::Key(Key::Z), Some(44), true)]
+        );
+        assert_eq!(
+            inputs(&event(EV_KEY, 44, 0)),
+            vec![(Input::Key(Key::Z), Some(44), false)]
+        );
+    }
+
+    #[test]
+    fn autorepeat_is_dropped() {
+        assert!(inputs(&event(EV_KEY, 44, 2)).is_empty());
+    }
+
+    #[test]
+    fn unknown_keys_get_x11_keycodes() {
+        assert_eq!(
+            inputs(&event(EV_KEY, 240, 1)),
+            vec![(Input::Key(Key::Unknown(248)), Some(240), true)]
+        );
+    }
+
+    #[test]
+    fn mouse_buttons_are_mouse_inputs() {
+        assert_eq!(
+            inputs(&event(EV_KEY, BTN_LEFT, 1)),
+            vec![(Input::Mouse(MouseButton::Left), None, true)]
+        );
+        assert_eq!(
+            inputs(&event(EV_KEY, BTN_EXTRA, 0)),
+            vec![(Input::Mouse(MouseButton::Forward), None, false)]
+        );
+    }
+
+    #[test]
+    fn touch_and_tool_buttons_are_dropped() {
+        for code in [
+            BTN_TOUCH,
+            BTN_TOOL_FINGER,
+            BTN_MISC,
+            BTN_DPAD_UP,
+            BTN_TRIGGER_HAPPY,
+        ] {
+            assert!(inputs(&event(EV_KEY, code, 1)).is_empty(), "{:#x}", code);
+        }
+    }
+
+    #[test]
+    fn keys_past_the_buttons_are_kept() {
+        assert_eq!(
+            inputs(&event(EV_KEY, KEY_FN, 1)),
+            vec![(Input::Key(Key::LFunction), Some(KEY_FN as u32), true)]
+        );
+    }
+
+    #[test]
+    fn wheel_notches_are_pulsed() {
+        assert_eq!(
+            inputs(&event(EV_REL, REL_WHEEL, -2)),
+            vec![
+                (Input::Wheel(WheelDirection::Down), None, true),
+                (Input::Wheel(WheelDirection::Down), None, false),
+                (Input::Wheel(WheelDirection::Down), None, true),
+                (Input::Wheel(WheelDirection::Down), None, false),
+            ]
+        );
+        assert_eq!(
+            inputs(&event(EV_REL, REL_HWHEEL, 1)),
+            vec![
+                (Input::Wheel(WheelDirection::Right), None, true),
+                (Input::Wheel(WheelDirection::Right), None, false),
+            ]
+        );
+    }
+
+    #[test]
+    fn other_events_are_dropped() {
+        // EV_SYN and EV_ABS
+        assert!(inputs(&event(0x00, 0, 0)).is_empty());
+        assert!(inputs(&event(0x03, 0, 120)).is_empty());
+    }
+}
@@ -0,0 +1,276 @@
+extern crate rdev;
+
+use std::sync::Arc;
+
+use rdev::{Event, EventType};
+
+use crate::{
+    helper,
+    input::{Input, InputEvent, Timestamp},
+};
+
+use super::{EventSink, InputSource, KeyProbe};
+
+// global hook through rdev, needs x11 on linux
+pub struct RdevSource;
+
+fn convert(event: Event) -> Vec<InputEvent> {
+    let time = Timestamp::new(event.time);
+
+    match event.event_type {
+        EventType::KeyPress(rdev_key) | EventType::KeyRelease(rdev_key) => {
+            let is_down = matches!(event.event_type, EventType::KeyPress(_));
+            let key = helper::rdev_to_key(rdev_key);
+            let scancode = helper::rdev_to_scancode(rdev_key);
+
+            vec![InputEvent::new(Input::Key(key), scancode, is_down, time)]
+        }
+
+        EventType::ButtonPress(rdev_button) | EventType::ButtonRelease(rdev_button) => {
+            let is_down = matches!(event.event_type, EventType::ButtonPress(_));
+
+            match helper::rdev_to_mouse(rdev_button) {
+                Ok(button) => vec![InputEvent::new(Input::Mouse(button), None, is_down, time)],
+                Err(_) => vec![],
+            }
+        }
+
+        EventType::Wheel { delta_x, delta_y } => helper::rdev_to_wheel(delta_x, delta_y)
+            .into_iter()
+            .flat_map(|direction| {
+                // the wheel has no release, pulse it instead
+                let input = Input::Wheel(direction);
+
+                [
+                    InputEvent::new(input.clone(), None, true, time),
+                    InputEvent::new(input, None, false, time),
+                ]
+            })
+            .collect(),
+
+        _ => vec![],
+    }
+}
+
+impl InputSource for RdevSource {
+    fn probe(&self) -> Option<Arc<dyn KeyProbe>> {
+        let probe = RdevProbe::open()?;
+        Some(Arc::new(probe))
+    }
+
+    fn listen(self: Box<Self>, mut sink: EventSink) -> anyhow::Result<()> {
+        let closure = move |event: Event| {
+            for input_event in self::convert(event) {
+                sink(input_event);
+            }
+        };
+
+        if let Err(err) = rdev::listen(closure) {
+            anyhow::bail!("{:?}", err);
+        }
+
+        Ok(())
+    }
+}
+
+// asks whatever rdev hooks into whether a key is down, None where that can't be done
+#[cfg(target_os = "linux")]
+pub struct RdevProbe {
+    display: std::sync::Mutex<XDisplay>,
+}
+
+#[cfg(target_os = "linux")]
+struct XDisplay(*mut x11::xlib::Display);
+
+// SAFETY: the display is only used behind the probe's mutex
+#[cfg(target_os = "linux")]
+unsafe impl Send for XDisplay {}
+
+#[cfg(target_os = "linux")]
+impl RdevProbe {
+    fn open() -> Option<Self> {
+        // xwayland only sees keys pressed in its own windows, everything else would read as up
+        if std::env::var("XDG_SESSION_TYPE").is_ok_and(|session| session == "wayland") {
+            return None;
+        }
+
+        // SAFETY: a null name opens whatever DISPLAY says
+        let display = unsafe { x11::xlib::XOpenDisplay(std::ptr::null()) };
+
+        (!display.is_null()).then(|| Self {
+            display: std::sync::Mutex::new(XDisplay(display)),
+        })
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl KeyProbe for RdevProbe {
+    fn is_held(&self, input: &Input, scancode: Option<u32>) -> Option<bool> {
+        use crate::input::MouseButton;
+        use x11::xlib;
+
+        let display = self.display.lock().unwrap();
+
+        match input {
+            // x11 keycodes are evdev codes moved up by 8
+            Input::Key(_) => {
+                let keycode = scancode? as usize + 8;
+                let mut keys = [0 as std::os::raw::c_char; 32];
+
+                if keycode >= keys.len() * 8 {
+                    return None;
+                }
+
+                // SAFETY: the keymap is always 32 bytes
+                unsafe { xlib::XQueryKeymap(display.0, keys.as_mut_ptr()) };
+
+                Some(keys[keycode / 8] as u8 & (1 << (keycode % 8)) != 0)
+            }
+
+            Input::Mouse(button) => {
+                let mask = match button {
+                    MouseButton::Left => xlib::Button1Mask,
+                    MouseButton::Middle => xlib::Button2Mask,
+                    MouseButton::Right => xlib::Button3Mask,
+                    _ => return None, // side buttons aren't in the pointer state
+                };
+
+                let (mut root, mut child) = (0, 0);
+                let (mut root_x, mut root_y, mut x, mut y) = (0, 0, 0, 0);
+                let mut state = 0;
+
+                // SAFETY: every out pointer is a local, the mask is set whatever screen it's on
+                unsafe {
+                    xlib::XQueryPointer(
+                        display.0,
+                        xlib::XDefaultRootWindow(display.0),
+                        &mut root,
+                        &mut child,
+                        &mut root_x,
+                        &mut root_y,
+                        &mut x,
+                        &mut y,
+                        &mut state,
+                    )
+                };
+
+                Some(state & mask != 0)
+            }
+
+            // pulsed, never held
+            Input::Wheel(_) => Some(false),
+        }
+    }
+}
+
+#[cfg(target_os = "windows")]
+pub struct RdevProbe;
+
+#[cfg(target_os = "windows")]
+impl RdevProbe {
+    fn open() -> Option<Self> {
+        Some(Self)
+    }
+}
+
+// scancodes below 89 are the same on windows, extended keys go by their virtual key instead
+#[cfg(target_os = "windows")]
+fn virtual_key(key: &crate::key::Key, scancode: Option<u32>) -> Option<i32> {
+    use crate::key::Key;
+    use winapi::um::winuser::*;
+
+    let virtual_key = match key {
+        Key::RControl => VK_RCONTROL,
+        Key::RAlt => VK_RMENU,
+        Key::LWindows => VK_LWIN,
+        Key::RWindows => VK_RWIN,
+        Key::Menu => VK_APPS,
+        Key::KpReturn => VK_RETURN,
+        Key::KpDivide => VK_DIVIDE,
+        Key::PrintScreen => VK_SNAPSHOT,
+        Key::Pause => VK_PAUSE,
+        Key::Insert => VK_INSERT,
+        Key::Delete => VK_DELETE,
+        Key::Home => VK_HOME,
+        Key::End => VK_END,
+        Key::PageUp => VK_PRIOR,
+        Key::PageDown => VK_NEXT,
+        Key::UpArrow => VK_UP,
+        Key::DownArrow => VK_DOWN,
+        Key::LeftArrow => VK_LEFT,
+        Key::RightArrow => VK_RIGHT,
+        Key::VolumeMute => VK_VOLUME_MUTE,
+        Key::VolumeDown => VK_VOLUME_DOWN,
+        Key::VolumeUp => VK_VOLUME_UP,
+        Key::MediaPlayPause => VK_MEDIA_PLAY_PAUSE,
+        Key::MediaStop => VK_MEDIA_STOP,
+        Key::MediaNext => VK_MEDIA_NEXT_TRACK,
+        Key::MediaPrevious => VK_MEDIA_PREV_TRACK,
+        Key::F13 => VK_F13,
+        Key::F14 => VK_F14,
+        Key::F15 => VK_F15,
+        Key::F16 => VK_F16,
+        Key::F17 => VK_F17,
+        Key::F18 => VK_F18,
+        Key::F19 => VK_F19,
+        Key::F20 => VK_F20,
+        Key::F21 => VK_F21,
+        Key::F22 => VK_F22,
+        Key::F23 => VK_F23,
+        Key::F24 => VK_F24,
+
+        _ => match scancode? {
+            scancode @ 1..=88 => {
+                // SAFETY: only reads the keyboard layout
+                match unsafe { MapVirtualKeyW(scancode, MAPVK_VSC_TO_VK_EX) } {
+                    0 => return None,
+                    virtual_key => virtual_key as i32,
+                }
+            }
+
+            _ => return None,
+        },
+    };
+
+    Some(virtual_key)
+}
+
+#[cfg(target_os = "windows")]
+impl KeyProbe for RdevProbe {
+    fn is_held(&self, input: &Input, scancode: Option<u32>) -> Option<bool> {
+        use crate::input::MouseButton;
+        use winapi::um::winuser::*;
+
+        let virtual_key = match input {
+            Input::Key(key) => self::virtual_key(key, scancode)?,
+            Input::Mouse(MouseButton::Left) => VK_LBUTTON,
+            Input::Mouse(MouseButton::Right) => VK_RBUTTON,
+            Input::Mouse(MouseButton::Middle) => VK_MBUTTON,
+            Input::Mouse(MouseButton::Back) => VK_XBUTTON1,
+            Input::Mouse(MouseButton::Forward) => VK_XBUTTON2,
+
+            // pulsed, never held
+            Input::Wheel(_) => return Some(false),
+        };
+
+        // SAFETY: any virtual key can be asked about, the high bit says it's down
+        Some(unsafe { GetAsyncKeyState(virtual_key) } < 0)
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "windows")))]
+pub struct RdevProbe;
+
+#[cfg(not(any(target_os = "linux", target_os = "windows")))]
+impl RdevProbe {
+    fn open() -> Option<Self> {
+        None
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "windows")))]
+impl KeyProbe for RdevProbe {
+    fn is_held(&self, _input: &Input, _scancode: Option<u32>) -> Option<bool> {
+        None
+    }
+}
@@ -0,0 +1,249 @@
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use anyhow::Context;
+
+use crate::{
+    helper,
+    input::{Bind, Input, InputEvent, Timestamp},
+    key::{Key, KeyBind, Modifier},
+};
+
+use super::{EventSink, InputSource};
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Action {
+    Down,
+    Up,
+    Tap,
+}
+
+struct Step {
+    delay: Duration,
+    action: Action,
+    input: Input,
+    modifiers: Vec<Input>, // pressed before the input and released after it
+}
+
+// plays synthetic input from a text file, one "<delay ms> <down|up|tap> <bind>" per line
+// modifiers in a bind ("^Z") are pressed around it with their left keys, or can be held
+// with their own down/up lines like any other key
+pub struct ScriptSource {
+    steps: Vec<Step>,
+}
+
+impl ScriptSource {
+    pub fn load(path: &str) -> anyhow::Result<Self> {
+        let script = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read input script {}", path))?;
+
+        Self::parse(&script)
+    }
+
+    pub fn parse(script: &str) -> anyhow::Result<Self> {
+        let mut steps = vec![];
+
+        for (i, line) in script.lines().enumerate() {
+            let line = line.trim();
+
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let parts: Vec<&str> = line.split_whitespace().collect();
+
+            let (delay, action, bind) = match parts[..] {
+                [delay, action, bind] => (delay, action, bind),
+                _ => anyhow::bail!("Line {}: expected \"<delay> <action> <bind>\"", i + 1),
+            };
+
+            let delay = delay
+                .parse::<u64>()
+                .with_context(|| format!("Line {}: invalid delay \"{}\"", i + 1, delay))?;
+
+            let action = match action.to_ascii_lowercase().as_str() {
+                "down" => Action::Down,
+                "up" => Action::Up,
+                "tap" => Action::Tap,
+                _ => anyhow::bail!("Line {}: unknown action \"{}\"", i + 1, action),
+            };
+
+            let parsed = Bind::deserialize(bind).ok();
+
+            let input = parsed
+                .as_ref()
+                .and_then(|bind| bind.input())
+                .with_context(|| format!("Line {}: unknown bind \"{}\"", i + 1, bind))?;
+
+            let modifiers = match parsed {
+                Some(Bind::Key(KeyBind { modifiers, .. })) => modifiers
+                    .iter()
+                    .map(|modifier| Input::Key(self::modifier_key(modifier)))
+                    .collect(),
+                _ => vec![],
+            };
+
+            steps.push(Step {
+                delay: Duration::from_millis(delay),
+                action,
+                input,
+                modifiers,
+            });
+        }
+
+        Ok(Self { steps })
+    }
+}
+
+fn modifier_key(modifier: &Modifier) -> Key {
+    match modifier {
+        Modifier::Control => Key::LControl,
+        Modifier::Alt => Key::LAlt,
+        Modifier::Shift => Key::LShift,
+        Modifier::Function => Key::LFunction,
+        Modifier::Meta => Key::LWindows,
+    }
+}
+
+fn send(sink: &mut EventSink, input: &Input, is_down: bool, time: Timestamp) {
+    let scancode = match input {
+        Input::Key(key) => helper::key_to_scancode(key),
+        _ => None,
+    };
+
+    sink(InputEvent::new(input.clone(), scancode, is_down, time));
+}
+
+impl InputSource for ScriptSource {
+    fn listen(self: Box<Self>, mut sink: EventSink) -> anyhow::Result<()> {
+        for step in self.steps {
+            thread::sleep(step.delay);
+
+            let time = Timestamp::new(SystemTime::now());
+
+            if step.action != Action::Up {
+                for modifier in &step.modifiers {
+                    self::send(&mut sink, modifier, true, time);
+                }
+
+                self::send(&mut sink, &step.input, true, time);
+            }
+
+            if step.action != Action::Down {
+                self::send(&mut sink, &step.input, false, time);
+
+                for modifier in step.modifiers.iter().rev() {
+                    self::send(&mut sink, modifier, false, time);
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::input::{MouseButton, WheelDirection};
+
+    fn parse_err(script: &str) -> String {
+        match ScriptSource::parse(script) {
+            Ok(_) => panic!("expected \"{}\" to fail", script),
+            Err(err) => format!("{:?}", err),
+        }
+    }
+
+    // every event the script sends, in order
+    fn play(script: &str) -> Vec<(Input, bool)> {
+        let source = Box::new(ScriptSource::parse(script).unwrap());
+        let events = Arc::new(Mutex::new(vec![]));
+
+        let sink_events = events.clone();
+        let sink = move |event: InputEvent| {
+            sink_events
+                .lock()
+                .unwrap()
+                .push((event.input, event.is_down));
+        };
+
+        source.listen(Box::new(sink)).unwrap();
+
+        let events = events.lock().unwrap().clone();
+        events
+    }
+
+    #[test]
+    fn lines_are_parsed() {
+        let source = ScriptSource::parse(
+            "# comment\n\n  0 down Z\n25 UP z\n100 tap MouseLeft\n5 tap WheelDown\n",
+        )
+        .unwrap();
+
+        let steps: Vec<(u64, Action, Input)> = source
+            .steps
+            .into_iter()
+            .map(|step| (step.delay.as_millis() as u64, step.action, step.input))
+            .collect();
+
+        assert_eq!(
+            steps,
+            vec![
+                (0, Action::Down, Input::Key(Key::Z)),
+                (25, Action::Up, Input::Key(Key::Z)),
+                (100, Action::Tap, Input::Mouse(MouseButton::Left)),
+                (5, Action::Tap, Input::Wheel(WheelDirection::Down)),
+            ]
+        );
+    }
+
+    #[test]
+    fn bad_lines_say_where_they_are() {
+        assert!(parse_err("0 tap Z\n0 tap").contains("Line 2"));
+        assert!(parse_err("0 tap Z extra").contains("Line 1"));
+        assert!(parse_err("soon tap Z").contains("invalid delay"));
+        assert!(parse_err("-5 tap Z").contains("invalid delay"));
+        assert!(parse_err("0 hold Z").contains("unknown action"));
+        assert!(parse_err("0 tap NotAKey").contains("unknown bind"));
+        assert!(parse_err("0 tap ...").contains("unknown bind"));
+    }
+
+    #[test]
+    fn taps_press_and_release() {
+        assert_eq!(
+            play("0 tap X"),
+            vec![(Input::Key(Key::X), true), (Input::Key(Key::X), false)]
+        );
+    }
+
+    #[test]
+    fn modifiers_are_held_around_the_bind() {
+        let shift = Input::Key(Key::LShift);
+        let control = Input::Key(Key::LControl);
+        let z = Input::Key(Key::Z);
+
+        assert_eq!(
+            play("0 tap ^_Z"),
+            vec![
+                (control.clone(), true),
+                (shift.clone(), true),
+                (z.clone(), true),
+                (z.clone(), false),
+                (shift.clone(), false),
+                (control.clone(), false),
+            ]
+        );
+
+        assert_eq!(
+            play("0 down _Z\n0 up _Z"),
+            vec![
+                (shift.clone(), true),
+                (z.clone(), true),
+                (z, false),
+                (shift, false),
+            ]
+        );
+    }
+}
@@ -0,0 +1,327 @@
+use std::time::{Duration, Instant};
+
+use crate::{
+    key::{Key, Modifier},
+    protocol::{Event, EventKind},
+};
+
+// typing that pauses this long starts a new run
+const TYPED_RUN_GAP: Duration = Duration::from_secs(2);
+
+// sent once when a run starts and never per keystroke, so lengths of passwords aren't given away
+const REDACTED: &str = "*";
+
+// what the platform calls the windows key
+#[cfg(target_os = "windows")]
+const META_NAME: &str = "Win";
+#[cfg(not(target_os = "windows"))]
+const META_NAME: &str = "Super";
+
+// shown in combos, sides of a modifier are the same key to a viewer
+pub fn display_name(key: &Key) -> String {
+    let name = match key {
+        Key::LControl | Key::RControl => "Ctrl",
+        Key::LAlt | Key::RAlt => "Alt",
+        Key::LShift | Key::RShift => "Shift",
+        Key::LWindows | Key::RWindows => self::META_NAME,
+        Key::LFunction => "Fn",
+        Key::Return | Key::KpReturn => "Enter",
+        Key::Escape => "Esc",
+        Key::UpArrow => "Up",
+        Key::DownArrow => "Down",
+        Key::LeftArrow => "Left",
+        Key::RightArrow => "Right",
+        Key::PageUp => "PgUp",
+        Key::PageDown => "PgDn",
+        Key::Delete => "Del",
+        Key::Insert => "Ins",
+        _ => return key.serialize(),
+    };
+
+    name.to_string()
+}
+
+fn modifier_name(modifier: &Modifier) -> &'static str {
+    match modifier {
+        Modifier::Control => "Ctrl",
+        Modifier::Alt => "Alt",
+        Modifier::Shift => "Shift",
+        Modifier::Function => "Fn",
+        Modifier::Meta => self::META_NAME,
+    }
+}
+
+// "Ctrl+Shift+P", modifiers are expected sorted
+pub fn combo(key: &Key, modifiers: &[Modifier]) -> String {
+    let mut parts: Vec<String> = modifiers
+        .iter()
+        .map(|modifier| self::modifier_name(modifier).to_string())
+        .collect();
+
+    parts.push(self::display_name(key));
+    parts.join("+")
+}
+
+// what the key types on a us layout, like key names in scancode mode
+fn typed_char(key: &Key, shift: bool) -> Option<char> {
+    let (plain, shifted) = match key {
+        Key::Space => (' ', ' '),
+        Key::Backtick => ('`', '~'),
+        Key::Num1 => ('1', '!'),
+        Key::Num2 => ('2', '@'),
+        Key::Num3 => ('3', '#'),
+        Key::Num4 => ('4', '$'),
+        Key::Num5 => ('5', '%'),
+        Key::Num6 => ('6', '^'),
+        Key::Num7 => ('7', '&'),
+        Key::Num8 => ('8', '*'),
+        Key::Num9 => ('9', '('),
+        Key::Num0 => ('0', ')'),
+        Key::Minus => ('-', '_'),
+        Key::Equals => ('=', '+'),
+        Key::OpenBracket => ('[', '{'),
+        Key::CloseBracket => (']', '}'),
+        Key::Backslash => ('\\', '|'),
+        Key::SemiColon => (';', ':'),
+        Key::Apostrophe => ('\'', '"'),
+        Key::Comma => (',', '<'),
+        Key::Period => ('.', '>'),
+        Key::ForwardSlash => ('/', '?'),
+
+        // letters are named by their capital
+        _ => {
+            let name = key.serialize();
+            let mut chars = name.chars();
+
+            return match (chars.next(), chars.next()) {
+                (Some(letter), None) if letter.is_ascii_uppercase() => match shift {
+                    true => Some(letter),
+                    false => Some(letter.to_ascii_lowercase()),
+                },
+                _ => None,
+            };
+        }
+    };
+
+    Some(if shift { shifted } else { plain })
+}
+
+#[derive(PartialEq, Debug)]
+pub enum Display {
+    Combo(String),
+    Typed { text: String, run: u64 }, // the whole run so far, replaces what was shown for it
+}
+
+impl Display {
+    pub fn to_event(&self) -> Event {
+        match self {
+            Display::Combo(combo) => Event {
+                label: Some(combo.clone()),
+                ..Event::now(EventKind::Combo)
+            },
+
+            Display::Typed { text, run } => Event {
+                label: Some(text.clone()),
+                ..Event::now(EventKind::Typed)
+            }
+            .with_payload(serde_json::json!({ "run": run })),
+        }
+    }
+}
+
+// turns presses into combos, with plain typing coalesced into runs of text
+pub struct TypedText {
+    text: String,
+    run: u64,
+    last_typed: Option<Instant>,
+}
+
+impl TypedText {
+    pub fn new() -> Self {
+        Self {
+            text: String::new(),
+            run: 0,
+            last_typed: None,
+        }
+    }
+
+    // redacted runs are only shown as they start, and then without the text
+    fn typed(&mut self, now: Instant, starts_run: bool, redact: bool) -> Option<Display> {
+        self.last_typed = Some(now);
+
+        let text = match (redact, starts_run) {
+            (false, _) => self.text.clone(),
+            (true, true) => REDACTED.to_string(),
+            (true, false) => return None,
+        };
+
+        Some(Display::Typed {
+            text,
+            run: self.run,
+        })
+    }
+
+    // what clients should show for a press, lone modifiers show nothing until they're used
+    // and redacted combos aren't shown at all
+    pub fn press(
+        &mut self,
+        key: &Key,
+        modifiers: &[Modifier],
+        now: Instant,
+        redact: bool,
+    ) -> Option<Display> {
+        if key.as_modifier().is_some() {
+            return None;
+        }
+
+        let in_run = self
+            .last_typed
+            .is_some_and(|last_typed| now.duration_since(last_typed) < TYPED_RUN_GAP);
+
+        let shift = match modifiers {
+            [] => Some(false),
+            [Modifier::Shift] => Some(true),
+            _ => None, // anything else is a shortcut, not typing
+        };
+
+        if let Some(typed) = shift.and_then(|shift| self::typed_char(key, shift)) {
+            if !in_run {
+                self.text.clear();
+                self.run += 1;
+            }
+
+            self.text.push(typed);
+            return self.typed(now, !in_run, redact);
+        }
+
+        if *key == Key::Backspace && modifiers.is_empty() && in_run && !self.text.is_empty() {
+            self.text.pop();
+            return self.typed(now, false, redact);
+        }
+
+        self.last_typed = None;
+
+        match redact {
+            true => None,
+            false => Some(Display::Combo(self::combo(key, modifiers))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn typed(text: &str, run: u64) -> Option<Display> {
+        Some(Display::Typed {
+            text: text.to_string(),
+            run,
+        })
+    }
+
+    fn combo(combo: &str) -> Option<Display> {
+        Some(Display::Combo(combo.to_string()))
+    }
+
+    #[test]
+    fn typing_coalesces_into_runs() {
+        let start = Instant::now();
+        let mut text = TypedText::new();
+
+        assert_eq!(text.press(&Key::H, &[], start, false), typed("h", 1));
+        assert_eq!(text.press(&Key::I, &[], start, false), typed("hi", 1));
+        assert_eq!(text.press(&Key::Space, &[], start, false), typed("hi ", 1));
+
+        // each press keeps the run going from when it was pressed
+        let later = start + TYPED_RUN_GAP - Duration::from_millis(1);
+        assert_eq!(text.press(&Key::Num1, &[], later, false), typed("hi 1", 1));
+
+        let after_gap = later + TYPED_RUN_GAP;
+        assert_eq!(text.press(&Key::A, &[], after_gap, false), typed("a", 2));
+    }
+
+    #[test]
+    fn shift_types_the_shifted_character() {
+        let now = Instant::now();
+        let mut text = TypedText::new();
+
+        // shift on its own shows nothing
+        assert_eq!(text.press(&Key::LShift, &[], now, false), None);
+
+        let shift = [Modifier::Shift];
+        assert_eq!(text.press(&Key::H, &shift, now, false), typed("H", 1));
+        assert_eq!(text.press(&Key::Num1, &shift, now, false), typed("H!", 1));
+        assert_eq!(
+            text.press(&Key::Apostrophe, &shift, now, false),
+            typed("H!\"", 1)
+        );
+        assert_eq!(
+            text.press(&Key::Apostrophe, &[], now, false),
+            typed("H!\"'", 1)
+        );
+    }
+
+    #[test]
+    fn backspace_edits_the_run() {
+        let start = Instant::now();
+        let mut text = TypedText::new();
+
+        text.press(&Key::A, &[], start, false);
+        text.press(&Key::B, &[], start, false);
+
+        assert_eq!(
+            text.press(&Key::Backspace, &[], start, false),
+            typed("a", 1)
+        );
+        assert_eq!(text.press(&Key::Backspace, &[], start, false), typed("", 1));
+
+        // nothing left to delete, or no run to delete from
+        assert_eq!(
+            text.press(&Key::Backspace, &[], start, false),
+            combo("Backspace")
+        );
+
+        let after_gap = start + TYPED_RUN_GAP;
+        text.press(&Key::C, &[], after_gap, false);
+
+        let next_run = after_gap + TYPED_RUN_GAP;
+        assert_eq!(
+            text.press(&Key::Backspace, &[], next_run, false),
+            combo("Backspace")
+        );
+    }
+
+    #[test]
+    fn shortcuts_are_combos_and_end_the_run() {
+        let now = Instant::now();
+        let mut text = TypedText::new();
+
+        text.press(&Key::A, &[], now, false);
+
+        let control_shift = [Modifier::Control, Modifier::Shift];
+        assert_eq!(
+            text.press(&Key::P, &control_shift, now, false),
+            combo("Ctrl+Shift+P")
+        );
+        assert_eq!(text.press(&Key::Return, &[], now, false), combo("Enter"));
+
+        assert_eq!(text.press(&Key::B, &[], now, false), typed("b", 2));
+    }
+
+    #[test]
+    fn redacted_runs_are_shown_once() {
+        let start = Instant::now();
+        let mut text = TypedText::new();
+
+        assert_eq!(text.press(&Key::P, &[], start, true), typed(REDACTED, 1));
+        assert_eq!(text.press(&Key::W, &[], start, true), None);
+        assert_eq!(text.press(&Key::Backspace, &[], start, true), None);
+        assert_eq!(text.press(&Key::C, &[Modifier::Control], start, true), None);
+
+        let after_gap = start + TYPED_RUN_GAP;
+        assert_eq!(
+            text.press(&Key::D, &[], after_gap, true),
+            typed(REDACTED, 2)
+        );
+    }
+}
@@ -1,3 +1,4 @@
 pub mod gui;
 pub mod keyboard;
+pub mod player;
 pub mod server;
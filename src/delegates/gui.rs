@@ -1,23 +1,207 @@
 extern crate eframe;
 extern crate egui;
-extern crate egui_keybinds;
 extern crate native_dialog;
 
 use std::io::Write;
 
 use anyhow::Context;
 use egui::{
-    vec2, Align, CentralPanel, Color32, Layout, RichText, ScrollArea, TextEdit, Ui, Widget,
+    vec2, Align, CentralPanel, Color32, ComboBox, DragValue, Layout, RichText, ScrollArea,
+    SelectableLabel, TextEdit, Ui,
 };
-use egui_keybinds::{KeyBind, KeyBindWidget};
 use native_dialog::FileDialog;
 
 use crate::{
     error::{self, ErrorStatus},
     helper,
-    settings::OverlaySettings,
+    input::{Bind, Input},
+    key::{Key, KeyBind, Modifier},
+    settings::{Backend, KeyEntry, OverlaySettings},
 };
 
+use super::keyboard;
+
+#[derive(PartialEq, Clone, Copy)]
+enum KeyTarget {
+    Key(usize),
+    Reset,
+    Record,
+    SaveReplay,
+    Pause,
+}
+
+fn egui_key(key: egui::Key) -> Key {
+    match key {
+        egui::Key::ArrowDown => Key::DownArrow,
+        egui::Key::ArrowLeft => Key::LeftArrow,
+        egui::Key::ArrowRight => Key::RightArrow,
+        egui::Key::ArrowUp => Key::UpArrow,
+        egui::Key::Escape => Key::Escape,
+        egui::Key::Tab => Key::Tab,
+        egui::Key::Backspace => Key::Backspace,
+        egui::Key::Enter => Key::Return,
+        egui::Key::Space => Key::Space,
+        egui::Key::Insert => Key::Insert,
+        egui::Key::Delete => Key::Delete,
+        egui::Key::Home => Key::Home,
+        egui::Key::End => Key::End,
+        egui::Key::PageUp => Key::PageUp,
+        egui::Key::PageDown => Key::PageDown,
+        egui::Key::Minus => Key::Minus,
+        egui::Key::PlusEquals => Key::Equals,
+        egui::Key::Num0 => Key::Num0,
+        egui::Key::Num1 => Key::Num1,
+        egui::Key::Num2 => Key::Num2,
+        egui::Key::Num3 => Key::Num3,
+        egui::Key::Num4 => Key::Num4,
+        egui::Key::Num5 => Key::Num5,
+        egui::Key::Num6 => Key::Num6,
+        egui::Key::Num7 => Key::Num7,
+        egui::Key::Num8 => Key::Num8,
+        egui::Key::Num9 => Key::Num9,
+        egui::Key::A => Key::A,
+        egui::Key::B => Key::B,
+        egui::Key::C => Key::C,
+        egui::Key::D => Key::D,
+        egui::Key::E => Key::E,
+        egui::Key::F => Key::F,
+        egui::Key::G => Key::G,
+        egui::Key::H => Key::H,
+        egui::Key::I => Key::I,
+        egui::Key::J => Key::J,
+        egui::Key::K => Key::K,
+        egui::Key::L => Key::L,
+        egui::Key::M => Key::M,
+        egui::Key::N => Key::N,
+        egui::Key::O => Key::O,
+        egui::Key::P => Key::P,
+        egui::Key::Q => Key::Q,
+        egui::Key::R => Key::R,
+        egui::Key::S => Key::S,
+        egui::Key::T => Key::T,
+        egui::Key::U => Key::U,
+        egui::Key::V => Key::V,
+        egui::Key::W => Key::W,
+        egui::Key::X => Key::X,
+        egui::Key::Y => Key::Y,
+        egui::Key::Z => Key::Z,
+        egui::Key::F1 => Key::F1,
+        egui::Key::F2 => Key::F2,
+        egui::Key::F3 => Key::F3,
+        egui::Key::F4 => Key::F4,
+        egui::Key::F5 => Key::F5,
+        egui::Key::F6 => Key::F6,
+        egui::Key::F7 => Key::F7,
+        egui::Key::F8 => Key::F8,
+        egui::Key::F9 => Key::F9,
+        egui::Key::F10 => Key::F10,
+        egui::Key::F11 => Key::F11,
+        egui::Key::F12 => Key::F12,
+        egui::Key::F13 => Key::F13,
+        egui::Key::F14 => Key::F14,
+        egui::Key::F15 => Key::F15,
+        egui::Key::F16 => Key::F16,
+        egui::Key::F17 => Key::F17,
+        egui::Key::F18 => Key::F18,
+        egui::Key::F19 => Key::F19,
+        egui::Key::F20 => Key::F20,
+    }
+}
+
+fn egui_modifiers(modifiers: egui::Modifiers) -> Vec<Modifier> {
+    let mut result = vec![];
+
+    if modifiers.ctrl || modifiers.mac_cmd {
+        result.push(Modifier::Control);
+    }
+
+    if modifiers.alt {
+        result.push(Modifier::Alt);
+    }
+
+    if modifiers.shift {
+        result.push(Modifier::Shift);
+    }
+
+    result
+}
+
+// the first key pressed in this window, for when the input backend can't see anything
+fn window_capture(ui: &Ui) -> Option<KeyBind> {
+    ui.input(|input| {
+        input.events.iter().find_map(|event| match event {
+            egui::Event::Key {
+                key,
+                pressed: true,
+                modifiers,
+                ..
+            } => Some(KeyBind::new(
+                Some(self::egui_key(*key)),
+                self::egui_modifiers(*modifiers),
+            )),
+            _ => None,
+        })
+    })
+}
+
+// keys are picked up by the keyboard thread, so anything it can see can be bound.
+// while the picker has focus the window's own key events count too, so binding still works
+// with the script backend or when the hook gets nothing (e.g. wayland without evdev)
+fn key_picker(ui: &mut Ui, bind: &mut KeyBind, target: KeyTarget, picking: &mut Option<KeyTarget>) {
+    let is_picking = *picking == Some(target);
+
+    let text = if is_picking {
+        "Press a key".to_string()
+    } else {
+        bind.serialize()
+    };
+
+    let response = ui.add_sized(vec2(80_f32, 20_f32), SelectableLabel::new(is_picking, text));
+
+    if response.clicked() {
+        if is_picking {
+            keyboard::cancel_capture();
+            response.surrender_focus();
+            *picking = None;
+        } else {
+            keyboard::begin_capture();
+            response.request_focus();
+            *picking = Some(target);
+        }
+    }
+
+    if response.secondary_clicked() {
+        *bind = KeyBind::empty();
+
+        if is_picking {
+            keyboard::cancel_capture();
+            response.surrender_focus();
+            *picking = None;
+        }
+    }
+
+    if *picking == Some(target) {
+        let captured = match keyboard::take_capture() {
+            Some(captured) => Some(captured),
+            None if response.has_focus() => {
+                // tab would move focus away instead of being bound
+                ui.memory_mut(|memory| memory.lock_focus(response.id, true));
+                self::window_capture(ui)
+            }
+            None => None,
+        };
+
+        if let Some(captured) = captured {
+            keyboard::cancel_capture();
+            response.surrender_focus();
+            *bind = captured;
+            *picking = None;
+        }
+
+        ui.ctx().request_repaint();
+    }
+}
+
 struct Gui {
     current_settings: OverlaySettings,
     saved_settings: OverlaySettings,
@@ -30,6 +214,10 @@ struct Gui {
     saved_toml: String,
 
     port_str: String,
+
+    picking: Option<KeyTarget>,
+
+    recording_status: String, // outcome of the last recording button
 }
 
 impl Gui {
@@ -46,6 +234,10 @@ impl Gui {
             saved_toml: settings.to_toml().unwrap(),
 
             port_str: settings.server.port.to_string(),
+
+            picking: None,
+
+            recording_status: String::new(),
         })
     }
 }
@@ -74,10 +266,11 @@ impl Gui {
                 self.saved_settings = self.current_settings.clone();
                 self.saved_toml = self.current_toml.clone();
 
-                let _ = super::keyboard::refresh_keys(
-                    self.saved_settings.keys.clone(),
-                    self.saved_settings.reset.clone(),
-                );
+                let _ = super::keyboard::refresh_keys(&self.saved_settings);
+                super::keyboard::refresh_recording(&self.saved_settings.recording);
+                super::player::refresh(&self.saved_settings);
+                super::keyboard::refresh_privacy(&self.saved_settings.privacy);
+                super::keyboard::broadcast_config();
             }
         });
     }
@@ -123,35 +316,118 @@ impl Gui {
                 )
             });
 
+            ui.horizontal(|h| {
+                h.label("Events Endpoint:");
+
+                h.add_sized(
+                    vec2(50_f32, 20_f32),
+                    TextEdit::singleline(&mut self.current_settings.web.events_endpoint)
+                        .hint_text(self.used_settings.web.events_endpoint.clone()),
+                )
+            });
+
             ui.label(format!(
                 "Local files are currently located at: {}",
                 self.current_settings.web.local_file_path
             ));
 
             if ui.button("Select Path").clicked() {
-                match FileDialog::new().show_open_single_dir() {
-                    Ok(result) => {
-                        if let Some(pathbuf) = result {
-                            if let Ok(path) = pathbuf.into_os_string().into_string() {
-                                self.current_settings.web.local_file_path = path;
-                            }
-                        }
+                if let Ok(Some(pathbuf)) = FileDialog::new().show_open_single_dir() {
+                    if let Ok(path) = pathbuf.into_os_string().into_string() {
+                        self.current_settings.web.local_file_path = path;
                     }
+                }
+            }
+        });
+
+        ui.collapsing("Input", |ui| {
+            let input = &mut self.current_settings.input;
+
+            ui.horizontal(|h| {
+                h.label("Backend:");
+
+                ComboBox::from_id_source("input_backend")
+                    .selected_text(format!("{:?}", input.backend))
+                    .show_ui(h, |ui| {
+                        ui.selectable_value(&mut input.backend, Backend::Rdev, "Rdev");
+                        ui.selectable_value(&mut input.backend, Backend::Script, "Script");
+                        ui.selectable_value(&mut input.backend, Backend::Evdev, "Evdev");
+                    });
+            });
+
+            if input.backend == Backend::Script {
+                ui.horizontal(|h| {
+                    h.label("Script Path:");
 
-                    _ => (),
+                    h.add_sized(
+                        vec2(100_f32, 20_f32),
+                        TextEdit::singleline(&mut input.script_path)
+                            .hint_text(self.used_settings.input.script_path.clone()),
+                    )
+                });
+            }
+
+            if input.backend == Backend::Evdev {
+                ui.label("Devices (names or paths, empty reads every device):");
+
+                input.evdev_devices.retain_mut(|device| {
+                    let mut was_deleted = false;
+
+                    ui.horizontal(|h| {
+                        h.add_sized(vec2(200_f32, 20_f32), TextEdit::singleline(device));
+                        was_deleted = h.button("-").clicked();
+                    });
+
+                    !was_deleted
+                });
+
+                if ui.button("+").clicked() {
+                    input.evdev_devices.push(String::new());
                 }
             }
         });
 
         ui.collapsing("Keyboard", |ui| {
             ui.collapsing("Keys", |ui| {
-                self.current_settings.keys.retain_mut(|pair| {
+                let mut row = 0;
+
+                self.current_settings.keys.retain_mut(|entry| {
                     let mut was_deleted = false;
 
                     ui.horizontal(|h| {
-                        KeyBindWidget::new(&mut pair.0).ui(h);
+                        let mut pointer = entry.bind.pointer_input();
+
+                        let source_text = match &pointer {
+                            Some(input) => input.serialize(),
+                            None => "Key".to_string(),
+                        };
+
+                        ComboBox::from_id_source(("key_source", row))
+                            .width(60_f32)
+                            .selected_text(source_text)
+                            .show_ui(h, |ui| {
+                                ui.selectable_value(&mut pointer, None, "Key");
+
+                                for input in Input::pointer_inputs() {
+                                    let text = input.serialize();
+                                    ui.selectable_value(&mut pointer, Some(input), text);
+                                }
+                            });
+
+                        match pointer {
+                            Some(input) => entry.bind = Bind::from(input),
+                            None if entry.bind.pointer_input().is_some() => {
+                                entry.bind = Bind::empty()
+                            }
+                            None => (),
+                        }
 
-                        let mut mask_str = if let Some(mask) = &pair.1 {
+                        if let Bind::Key(key_bind) = &mut entry.bind {
+                            let target = KeyTarget::Key(row);
+                            self::key_picker(h, key_bind, target, &mut self.picking);
+                        }
+
+                        let mut mask_str = if let Some(mask) = &entry.mask {
                             mask.clone()
                         } else {
                             "".to_string()
@@ -159,26 +435,208 @@ impl Gui {
 
                         h.add_sized(
                             vec2(50_f32, 20_f32),
-                            TextEdit::singleline(&mut mask_str).hint_text(pair.0.serialize()),
+                            TextEdit::singleline(&mut mask_str).hint_text(entry.bind.serialize()),
                         );
 
-                        pair.1 = if mask_str == "" { None } else { Some(mask_str) };
+                        entry.mask = if mask_str.is_empty() {
+                            None
+                        } else {
+                            Some(mask_str)
+                        };
+
+                        let mut device_str = entry.device.clone().unwrap_or_default();
+
+                        h.add_sized(
+                            vec2(60_f32, 20_f32),
+                            TextEdit::singleline(&mut device_str).hint_text("Any device"),
+                        );
+
+                        entry.device = if device_str.is_empty() {
+                            None
+                        } else {
+                            Some(device_str)
+                        };
 
                         was_deleted = h.button("-").clicked();
                     });
 
-                    return !was_deleted;
+                    // rows after this one move up, stop picking rather than bind the wrong one
+                    if was_deleted && matches!(self.picking, Some(KeyTarget::Key(_))) {
+                        keyboard::cancel_capture();
+                        self.picking = None;
+                    }
+
+                    row += 1;
+                    !was_deleted
                 });
 
                 if ui.button("+").clicked() {
-                    self.current_settings.keys.push((KeyBind::empty(), None));
+                    self.current_settings.keys.push(KeyEntry::empty());
                 }
             });
 
+            ui.horizontal(|h| {
+                h.label("Debounce:");
+
+                h.add(
+                    DragValue::new(&mut self.current_settings.toml_settings.keyboard.debounce_ms)
+                        .clamp_range(0..=100)
+                        .suffix("ms"),
+                );
+            });
+
+            ui.horizontal(|h| {
+                h.label("Stuck Key Timeout:").on_hover_text(
+                    "Lets go of keys held this long when the input backend can't check them, \
+                     0 never does. Keys really held down are pressed again by autorepeat",
+                );
+
+                h.add(
+                    DragValue::new(
+                        &mut self
+                            .current_settings
+                            .toml_settings
+                            .keyboard
+                            .stuck_timeout_ms,
+                    )
+                    .clamp_range(0..=600000)
+                    .speed(100)
+                    .suffix("ms"),
+                );
+            });
+
+            let keyboard = &mut self.current_settings.toml_settings.keyboard;
+            ui.checkbox(
+                &mut keyboard.capture_all,
+                "Show every key as combos and typed text",
+            );
+            ui.checkbox(
+                &mut keyboard.catch_all,
+                "Show unbound keys in a catch-all slot",
+            );
+
             ui.horizontal(|h| {
                 h.label("Reset:");
-                KeyBindWidget::new(&mut self.current_settings.reset).ui(h);
+                let reset = &mut self.current_settings.reset;
+                self::key_picker(h, reset, KeyTarget::Reset, &mut self.picking);
+            });
+        });
+
+        ui.collapsing("Recording", |ui| {
+            ui.horizontal(|h| {
+                h.label("Directory:");
+
+                h.add_sized(
+                    vec2(100_f32, 20_f32),
+                    TextEdit::singleline(&mut self.current_settings.recording.directory)
+                        .hint_text(self.used_settings.recording.directory.clone()),
+                )
+            });
+
+            ui.horizontal(|h| {
+                h.label("Replay Length:");
+
+                h.add(
+                    DragValue::new(&mut self.current_settings.recording.replay_seconds)
+                        .clamp_range(1..=3600)
+                        .suffix("s"),
+                );
             });
+
+            ui.horizontal(|h| {
+                h.label("Record:");
+                let record = &mut self.current_settings.record;
+                self::key_picker(h, record, KeyTarget::Record, &mut self.picking);
+            });
+
+            ui.horizontal(|h| {
+                h.label("Save Replay:");
+                let save_replay = &mut self.current_settings.save_replay;
+                self::key_picker(h, save_replay, KeyTarget::SaveReplay, &mut self.picking);
+            });
+
+            ui.horizontal(|h| {
+                let recording_path = keyboard::recording_path();
+
+                let text = if recording_path.is_some() {
+                    "Stop Recording"
+                } else {
+                    "Start Recording"
+                };
+
+                if h.button(text).clicked() {
+                    let result = match recording_path {
+                        Some(_) => keyboard::stop_recording().map(|path| path.unwrap_or_default()),
+                        None => keyboard::start_recording(),
+                    };
+
+                    self.recording_status = match result {
+                        Ok(path) => path,
+                        Err(err) => format!("{:?}", err),
+                    };
+                }
+
+                if h.button("Save Replay").clicked() {
+                    self.recording_status = match keyboard::save_replay() {
+                        Ok(path) => path,
+                        Err(err) => format!("{:?}", err),
+                    };
+                }
+            });
+
+            if !self.recording_status.is_empty() {
+                ui.label(&self.recording_status);
+            }
+        });
+
+        ui.collapsing("osu! Replays", |ui| {
+            let osu = &mut self.current_settings.osu;
+
+            for (label, bind) in [
+                ("M1:", &mut osu.m1),
+                ("M2:", &mut osu.m2),
+                ("K1:", &mut osu.k1),
+                ("K2:", &mut osu.k2),
+            ] {
+                ui.horizontal(|h| {
+                    h.label(label);
+                    h.add_sized(vec2(80_f32, 20_f32), TextEdit::singleline(bind))
+                });
+            }
+        });
+
+        ui.collapsing("Privacy", |ui| {
+            ui.horizontal(|h| {
+                h.label("Pause:");
+                let pause = &mut self.current_settings.pause;
+                self::key_picker(h, pause, KeyTarget::Pause, &mut self.picking);
+            });
+
+            let privacy = &mut self.current_settings.privacy;
+            ui.checkbox(&mut privacy.redact, "Hide which keys are pressed");
+
+            ui.label("Pause while the focused window's title or class contains:");
+
+            privacy.auto_pause.retain_mut(|rule| {
+                let mut was_deleted = false;
+
+                ui.horizontal(|h| {
+                    h.add_sized(vec2(200_f32, 20_f32), TextEdit::singleline(rule));
+                    was_deleted = h.button("-").clicked();
+                });
+
+                !was_deleted
+            });
+
+            if ui.button("+").clicked() {
+                privacy.auto_pause.push(String::new());
+            }
+
+            if let Some(err) = keyboard::focus_error() {
+                ui.label(
+                    RichText::new(format!("Auto-pause won't work: {}", err)).color(Color32::RED),
+                );
+            }
         });
     }
 
@@ -273,7 +731,7 @@ pub fn start(settings: OverlaySettings) -> anyhow::Result<()> {
 
     let gui = Gui::new(settings).with_context(|| "Failed to initialize Gui struct")?;
 
-    if let Err(_) = eframe::run_native(crate::TITLE, options, Box::new(|_| Box::new(gui))) {
+    if eframe::run_native(crate::TITLE, options, Box::new(|_| Box::new(gui))).is_err() {
         anyhow::bail!("Failed to run eframe native window");
     }
 
@@ -1,125 +1,1137 @@
-extern crate egui_keybinds;
 extern crate lazy_static;
-extern crate rdev;
 
-use egui_keybinds::{KeyBind, KeyCode};
+use anyhow::Context;
 use lazy_static::lazy_static;
-use rdev::{Event, EventType};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::mpsc::Receiver;
 use std::sync::mpsc::{self, Sender};
-use std::sync::RwLock;
+use std::sync::{Arc, Mutex, RwLock};
 use std::thread;
+use std::time::{Duration, Instant, SystemTime};
 
-use crate::{helper, settings::OverlaySettings};
+use crate::{
+    backends::{self, KeyProbe},
+    combo::TypedText,
+    helper,
+    input::{Device, Input, InputEvent, Timestamp},
+    key::{Key, KeyBind, Modifier},
+    message::KeyMessage,
+    protocol::{self, Event, EventKind},
+    rate::{RateWindow, Rates},
+    record::{self, Record, RecordBinding, Recorder},
+    settings::{BindingMode, KeyEntry, OverlaySettings, PrivacySettings, RecordingSettings},
+    state::{Control, KeyboardState, Outcome, Transition},
+    window,
+};
 
 use super::server;
 
 lazy_static! {
-    static ref KEYS: RwLock<Vec<(KeyBind, Option<String>)>> = RwLock::new(Vec::new());
-    static ref RESET: RwLock<KeyBind> = RwLock::new(KeyBind::empty());
-    static ref HELD_KEYS: RwLock<Vec<KeyCode>> = RwLock::new(Vec::new());
+    // bindings and what's held, events are applied to it one at a time
+    static ref STATE: Mutex<KeyboardState> = Mutex::new(KeyboardState::new());
+
+    // press counts keyed by serialized bind, so they survive reordering the key list
+    static ref COUNTERS: RwLock<HashMap<String, u64>> = RwLock::new(HashMap::new());
+
+    // held bindings are let go after this unless the backend can tell they're still down
+    static ref STUCK_TIMEOUT_MS: RwLock<u64> = RwLock::new(0);
+    static ref PROBE: RwLock<Option<Arc<dyn KeyProbe>>> = RwLock::new(None);
+
+    // filtered presses keyed by serialized bind, like the counters
+    static ref FILTERED: RwLock<HashMap<String, u64>> = RwLock::new(HashMap::new());
+
+    static ref RATES: Mutex<RateWindow> = Mutex::new(RateWindow::new(Duration::from_secs(1)));
+
+    static ref RECORDER: Mutex<Recorder> = Mutex::new(Recorder::new(Duration::from_secs(30)));
+    static ref RECORDING_DIRECTORY: RwLock<String> = RwLock::new(String::new());
+
+    // recordings are written from here so the input hook never waits on the disk
+    static ref RECORDING_JOBS: Mutex<Sender<RecordingJob>> = Mutex::new(spawn_recording_writer());
+
+    // window title or class fragments that pause input while focused
+    static ref AUTO_PAUSE: RwLock<Vec<String>> = RwLock::new(Vec::new());
+    static ref FOCUS_ERROR: RwLock<Option<String>> = RwLock::new(None);
+
+    static ref TYPED_TEXT: Mutex<TypedText> = Mutex::new(TypedText::new());
+
+    // the next bind pressed while the gui is picking a key
+    static ref CAPTURED: Mutex<Option<KeyBind>> = Mutex::new(None);
+}
+
+static CAPTURING: AtomicBool = AtomicBool::new(false);
+
+static CAPTURE_ALL: AtomicBool = AtomicBool::new(false);
+
+// paused by the hotkey or by the focused window, either one stops anything being sent
+static PAUSED_BY_HOTKEY: AtomicBool = AtomicBool::new(false);
+static PAUSED_BY_WINDOW: AtomicBool = AtomicBool::new(false);
+static REDACT: AtomicBool = AtomicBool::new(false);
+
+static COUNTERS_DIRTY: AtomicBool = AtomicBool::new(false);
+const COUNTERS_SAVE_INTERVAL: Duration = Duration::from_secs(5);
+
+const RECORDING_FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+// file work for recordings, done in order on the writer thread
+// the senders are told how it went, dropping the receiver is fine
+enum RecordingJob {
+    Start(String, Sender<anyhow::Result<()>>),
+    Write(String, Vec<u8>),
+    Stop(String, Vec<u8>, Sender<anyhow::Result<()>>),
+    SaveReplay(String, Vec<u8>, Sender<anyhow::Result<()>>),
+}
+
+// rates are checked every tick and sent when they change, or every RATES_HEARTBEAT ticks
+const RATES_TICK: Duration = Duration::from_millis(100);
+const RATES_HEARTBEAT: u32 = 10;
+
+// no bind serializes to this
+const CATCH_ALL_COUNTER: &str = "*";
+
+const STUCK_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+// a configured binding as snapshots describe it
+#[derive(Serialize)]
+pub struct BindingState {
+    index: usize,
+    key: Option<String>, // the same as events carry
+    id: Option<String>,
+    bind: Option<String>,
+    mask: Option<String>,
+    label: String, // the mask, or the bind when there's none
+    count: u64,
+}
+
+#[derive(Serialize)]
+pub struct Config {
+    bindings: Vec<BindingState>,
+    catch_all: bool,
+}
+
+#[derive(Serialize)]
+pub struct Snapshot {
+    version: u32,
+    bindings: Vec<BindingState>,
+    catch_all: bool, // unbound keys show up at the index after the last binding
+    held: Vec<HeldKey>,
+    paused: bool,
+    reason: Option<&'static str>,
+    rates: Rates,
 }
 
-pub fn refresh_keys(keys: Vec<(KeyBind, Option<String>)>, reset: KeyBind) -> anyhow::Result<()> {
-    match KEYS.write() {
-        Ok(mut lock) => *lock = keys,
+// a held binding as resyncs describe it
+#[derive(Serialize)]
+pub struct HeldKey {
+    index: usize,
+    key: Option<String>,
+    id: Option<String>,
+    label: String,
+    since_ms: f64,
+}
+
+// asking the os is too slow for the input hook, events only read what the last check found
+const FOCUS_CHECK_INTERVAL: Duration = Duration::from_millis(100);
+const REDACTED_LABEL: &str = "*";
+
+pub fn refresh_keys(settings: &OverlaySettings) -> anyhow::Result<()> {
+    let controls = vec![
+        (Control::Reset, settings.reset.clone()),
+        (Control::Record, settings.record.clone()),
+        (Control::SaveReplay, settings.save_replay.clone()),
+        (Control::Pause, settings.pause.clone()),
+    ];
+
+    let keyboard = &settings.toml_settings.keyboard;
+
+    match STATE.lock() {
+        Ok(mut lock) => {
+            // clients would keep drawing keys held through a save as down
+            if !self::is_paused() {
+                self::release_everything(&mut lock, Timestamp::new(SystemTime::now()));
+            }
+
+            lock.set_bindings(settings.keys.clone(), controls, keyboard.debounce_ms);
+            lock.set_catch_all(keyboard.catch_all);
+        }
+
         Err(err) => anyhow::bail!("{:?}", err),
     }
 
-    match RESET.write() {
-        Ok(mut lock) => *lock = reset,
+    CAPTURE_ALL.store(keyboard.capture_all, Ordering::Relaxed);
+
+    match STUCK_TIMEOUT_MS.write() {
+        Ok(mut lock) => *lock = settings.toml_settings.keyboard.stuck_timeout_ms,
         Err(err) => anyhow::bail!("{:?}", err),
     }
 
     Ok(())
 }
 
-fn on_key_interact(rdev_key: rdev::Key, is_down: bool) -> anyhow::Result<()> {
-    let keys = KEYS.read().unwrap().clone();
-    let reset = RESET.read().unwrap().clone();
+pub fn keys() -> Vec<KeyEntry> {
+    STATE.lock().unwrap().keys().to_vec()
+}
 
-    if let Ok(keycode) = helper::rdev_to_egui(rdev_key) {
-        if reset.key.is_some() && reset.key.unwrap() == keycode.clone() {
-            if !is_down {
-                // sent "reset" to clients
-                server::update_clients("reset".to_string());
-            }
+// the bind of the binding with this id, if it's still configured
+pub fn bind_of(id: &str) -> Option<String> {
+    let state = STATE.lock().unwrap();
 
-            return Ok(());
-        }
+    state
+        .keys()
+        .iter()
+        .find(|entry| entry.id == id)
+        .map(|entry| entry.bind.serialize())
+}
 
-        for (i, pair) in keys.iter().enumerate() {
-            let (bind, mask) = pair.clone();
+fn load_counters() -> anyhow::Result<()> {
+    if helper::is_first_run(crate::COUNTERS_FILENAME) {
+        return Ok(());
+    }
 
-            if bind.key.is_none() {
-                continue;
-            }
+    let counters_string = std::fs::read_to_string(crate::COUNTERS_FILENAME)
+        .with_context(|| "Failed to read from counters file")?;
 
-            let bind_key = bind.key.unwrap().clone();
+    let counters = toml::from_str::<HashMap<String, u64>>(&counters_string)
+        .with_context(|| "Failed to deserialize counters")?;
 
-            if bind_key != keycode.clone() {
-                continue;
-            }
+    match COUNTERS.write() {
+        Ok(mut lock) => *lock = counters,
+        Err(err) => anyhow::bail!("{:?}", err),
+    }
 
-            let held_keys_reader = HELD_KEYS.read().unwrap().clone();
-            let mut held_keys_writer = HELD_KEYS.write().unwrap();
+    Ok(())
+}
+
+fn save_counters() -> anyhow::Result<()> {
+    let data = toml::to_string_pretty(&*COUNTERS.read().unwrap())
+        .with_context(|| "Failed to serialize counters")?;
+
+    std::fs::write(crate::COUNTERS_FILENAME, data)
+        .with_context(|| "Failed to write to counters file")?;
+
+    Ok(())
+}
+
+// nothing saves on a timer once the process exits, so whatever's left is written out now
+pub fn on_shutdown() {
+    if COUNTERS_DIRTY.swap(false, Ordering::Relaxed) {
+        let _ = self::save_counters();
+    }
+
+    let _ = self::stop_recording();
+}
+
+// this is the input hook, the saver thread writes the cleared counters out
+fn reset_counters() {
+    COUNTERS.write().unwrap().clear();
+    FILTERED.write().unwrap().clear();
+    COUNTERS_DIRTY.store(true, Ordering::Relaxed);
+}
+
+pub fn refresh_recording(settings: &RecordingSettings) {
+    let replay_window = Duration::from_secs(settings.replay_seconds);
+    RECORDER.lock().unwrap().set_buffer_window(replay_window);
+
+    *RECORDING_DIRECTORY.write().unwrap() = settings.directory.clone();
+}
+
+pub fn recording_path() -> Option<String> {
+    RECORDER.lock().unwrap().recording_path()
+}
+
+fn spawn_recording_writer() -> Sender<RecordingJob> {
+    let (sender, jobs) = mpsc::channel();
+    thread::spawn(move || self::write_recordings(jobs));
+
+    sender
+}
+
+fn write_recordings(jobs: Receiver<RecordingJob>) {
+    let mut writer = record::Writer::default();
 
-            if is_down {
-                if held_keys_reader.contains(&bind_key) {
-                    continue;
+    for job in jobs {
+        match job {
+            RecordingJob::Start(path, done) => {
+                let result = writer.start(&path);
+
+                if result.is_err() {
+                    RECORDER.lock().unwrap().abandon(&path);
                 }
 
-                (*held_keys_writer).push(keycode.clone());
-            } else {
-                if !held_keys_reader.contains(&bind_key) {
-                    continue;
+                let _ = done.send(result);
+            }
+
+            RecordingJob::Write(path, bytes) => {
+                // a failed write ends the recording, the gui shows it stopped
+                if writer.write(&path, &bytes).is_err() {
+                    RECORDER.lock().unwrap().abandon(&path);
                 }
+            }
 
-                (*held_keys_writer).retain(|k| *k != keycode);
+            RecordingJob::Stop(path, bytes, done) => {
+                let _ = done.send(writer.stop(&path, &bytes));
             }
 
-            let displayed_key = if let Some(str) = mask {
-                str
+            RecordingJob::SaveReplay(path, bytes, done) => {
+                let _ = done.send(record::write_replay(&path, &bytes));
+            }
+        }
+    }
+}
+
+// queued while the recorder is locked, so a session's jobs can't overtake each other
+fn queue_recording_job(job: RecordingJob) {
+    let _ = RECORDING_JOBS.lock().unwrap().send(job);
+}
+
+fn finished(result: Receiver<anyhow::Result<()>>) -> anyhow::Result<()> {
+    result.recv().context("Recording writer stopped")?
+}
+
+fn begin_recording() -> anyhow::Result<(String, Receiver<anyhow::Result<()>>)> {
+    let path = record::file_name(&RECORDING_DIRECTORY.read().unwrap(), "session");
+    let (done, result) = mpsc::channel();
+
+    let mut recorder = RECORDER.lock().unwrap();
+    recorder.start(path.clone())?;
+    self::queue_recording_job(RecordingJob::Start(path.clone(), done));
+
+    Ok((path, result))
+}
+
+fn end_recording() -> Option<(String, Receiver<anyhow::Result<()>>)> {
+    let (done, result) = mpsc::channel();
+
+    let mut recorder = RECORDER.lock().unwrap();
+    let (path, bytes) = recorder.stop()?;
+    self::queue_recording_job(RecordingJob::Stop(path.clone(), bytes, done));
+
+    Some((path, result))
+}
+
+fn begin_replay() -> (String, Receiver<anyhow::Result<()>>) {
+    let path = record::file_name(&RECORDING_DIRECTORY.read().unwrap(), "replay");
+    let (done, result) = mpsc::channel();
+
+    let bytes = RECORDER.lock().unwrap().replay();
+    self::queue_recording_job(RecordingJob::SaveReplay(path.clone(), bytes, done));
+
+    (path, result)
+}
+
+// returns the path being recorded to
+pub fn start_recording() -> anyhow::Result<String> {
+    let (path, result) = self::begin_recording()?;
+    self::finished(result)?;
+
+    Ok(path)
+}
+
+// returns the path of the finished recording, if there was one
+pub fn stop_recording() -> anyhow::Result<Option<String>> {
+    match self::end_recording() {
+        Some((path, result)) => {
+            self::finished(result)?;
+            Ok(Some(path))
+        }
+
+        None => Ok(None),
+    }
+}
+
+// returns the path the replay was saved to
+pub fn save_replay() -> anyhow::Result<String> {
+    let (path, result) = self::begin_replay();
+    self::finished(result)?;
+
+    Ok(path)
+}
+
+fn run_control(control: Control) {
+    match control {
+        Control::Reset => {
+            self::reset_counters();
+
+            // sent "reset" to clients
+            server::update_clients(Some("reset".to_string()), Event::now(EventKind::Reset));
+        }
+
+        // this is the input hook, the writer is left to it without waiting.
+        // errors have nowhere to go anyway, the gui shows whether it worked
+        Control::Record => {
+            if self::recording_path().is_some() {
+                let _ = self::end_recording();
             } else {
-                bind_key.serialize()
-            };
+                let _ = self::begin_recording();
+            }
+        }
+
+        Control::SaveReplay => {
+            let _ = self::begin_replay();
+        }
 
-            let data = format!("[\"{}\", {}, {}]", displayed_key, is_down, i);
-            server::update_clients(data);
+        Control::Pause => {
+            let paused = !PAUSED_BY_HOTKEY.load(Ordering::Relaxed);
+            self::set_paused(&PAUSED_BY_HOTKEY, paused);
         }
     }
+}
+
+pub fn refresh_privacy(settings: &PrivacySettings) {
+    REDACT.store(settings.redact, Ordering::Relaxed);
+
+    *AUTO_PAUSE.write().unwrap() = settings
+        .auto_pause
+        .iter()
+        .filter(|rule| !rule.trim().is_empty())
+        .cloned()
+        .collect();
+}
+
+// why auto-pause can't work, only set while there are rules
+pub fn focus_error() -> Option<String> {
+    FOCUS_ERROR.read().unwrap().clone()
+}
+
+pub fn is_paused() -> bool {
+    PAUSED_BY_HOTKEY.load(Ordering::Relaxed) || PAUSED_BY_WINDOW.load(Ordering::Relaxed)
+}
+
+fn pause_reason() -> Option<&'static str> {
+    if PAUSED_BY_HOTKEY.load(Ordering::Relaxed) {
+        Some("hotkey")
+    } else if PAUSED_BY_WINDOW.load(Ordering::Relaxed) {
+        Some("window")
+    } else {
+        None
+    }
+}
+
+pub fn paused_event() -> Event {
+    let payload = serde_json::json!({
+        "paused": self::is_paused(),
+        "reason": self::pause_reason(),
+    });
+
+    Event::now(EventKind::Paused).with_payload(payload)
+}
+
+// sets one reason to pause, clients hear about it when that pauses or resumes everything
+fn set_paused(reason: &AtomicBool, paused: bool) {
+    let mut state = STATE.lock().unwrap();
+    let time = Timestamp::new(SystemTime::now());
+
+    let was_paused = self::is_paused();
+    reason.store(paused, Ordering::Relaxed);
+
+    match (was_paused, self::is_paused()) {
+        // clients would keep showing whatever was held
+        (false, true) => self::release_everything(&mut state, time),
+
+        // whatever went down while paused was never shown, so it's let go quietly
+        (true, false) => {
+            for (i, _) in state.held() {
+                state.force_release(i, &time);
+            }
+
+            state.release_catch_all(&time);
+        }
+
+        _ => return,
+    }
+
+    server::update_clients(None, self::paused_event());
+}
+
+// pauses while a window matching an auto-pause rule has focus
+fn check_focus() {
+    let rules = AUTO_PAUSE.read().unwrap().clone();
+
+    let matched = match rules.is_empty() {
+        true => {
+            *FOCUS_ERROR.write().unwrap() = None;
+            false
+        }
+
+        false => match window::focused() {
+            Ok(focused) => {
+                *FOCUS_ERROR.write().unwrap() = None;
+                focused.is_some_and(|window| rules.iter().any(|rule| window.matches(rule)))
+            }
+
+            Err(err) => {
+                *FOCUS_ERROR.write().unwrap() = Some(format!("{:?}", err));
+                false
+            }
+        },
+    };
+
+    if matched != PAUSED_BY_WINDOW.load(Ordering::Relaxed) {
+        self::set_paused(&PAUSED_BY_WINDOW, matched);
+    }
+}
+
+// the only place focus is checked, so the input hook never waits on the os
+fn watch_focus() {
+    loop {
+        thread::sleep(FOCUS_CHECK_INTERVAL);
+        self::check_focus();
+    }
+}
+
+pub fn begin_capture() {
+    *CAPTURED.lock().unwrap() = None;
+    CAPTURING.store(true, Ordering::Relaxed);
+}
+
+pub fn cancel_capture() {
+    CAPTURING.store(false, Ordering::Relaxed);
+}
+
+pub fn take_capture() -> Option<KeyBind> {
+    CAPTURED.lock().unwrap().take()
+}
+
+// same rules as the old egui_keybinds widget: the first non-modifier press is the bind,
+// releasing a modifier on its own binds the modifier key
+fn capture(
+    key: Key,
+    scancode: Option<u32>,
+    is_down: bool,
+    modifiers: Vec<Modifier>,
+    binding_mode: BindingMode,
+) -> bool {
+    if !CAPTURING.load(Ordering::Relaxed) || is_down == key.as_modifier().is_some() {
+        return false;
+    }
+
+    // store where the key is rather than what the layout calls it
+    let key = match (binding_mode, scancode) {
+        (BindingMode::Scancode, Some(scancode)) => helper::scancode_to_key(scancode).unwrap_or(key),
+        _ => key,
+    };
+
+    *CAPTURED.lock().unwrap() = Some(KeyBind::new(Some(key), modifiers));
+    CAPTURING.store(false, Ordering::Relaxed);
+
+    true
+}
+
+pub fn on_key_interact(event: InputEvent) -> anyhow::Result<()> {
+    let input = event.input.serialize();
+    let is_down = event.is_down;
+    let time = event.time;
+
+    let triggered = match self::dispatch(event)? {
+        Some(triggered) => triggered,
+        None => return Ok(()),
+    };
+
+    // one record per triggered binding, or a single unbound one
+    let mut bindings: Vec<Option<RecordBinding>> = triggered.into_iter().map(Some).collect();
+
+    if bindings.is_empty() {
+        bindings.push(None);
+    }
+
+    let records = bindings.into_iter().map(|binding| Record {
+        input: input.clone(),
+        is_down,
+        unix_ms: time.unix_ms(),
+        monotonic_ns: time.monotonic_ns,
+        binding,
+    });
+
+    let mut recorder = RECORDER.lock().unwrap();
+
+    for record in records {
+        recorder.push(record);
+    }
 
     Ok(())
 }
 
+fn label(entry: &KeyEntry) -> String {
+    match &entry.mask {
+        Some(mask) => mask.clone(),
+        None => entry
+            .bind
+            .input()
+            .map_or_else(String::new, |input| input.serialize()),
+    }
+}
+
+// redacted labels keep presses visible without saying which key they were
+fn shown_label(label: &str) -> &str {
+    match REDACT.load(Ordering::Relaxed) {
+        true => REDACTED_LABEL,
+        false => label,
+    }
+}
+
+// key is the bind, id the binding's id which stays the same when the list is reordered
+fn send_transition(
+    id: &str,
+    key: &str,
+    label: &str,
+    transition: Transition,
+    count: u64,
+    time: Timestamp,
+    device: Option<&Device>,
+) {
+    let redact = REDACT.load(Ordering::Relaxed);
+
+    let message = KeyMessage {
+        key: if redact { None } else { Some(key) },
+        id: if redact { None } else { Some(id) },
+        label: self::shown_label(label),
+        is_down: transition.is_down,
+        index: transition.index,
+        count,
+        time,
+        hold_ns: transition.hold_ns,
+        device: if redact { None } else { device },
+    };
+
+    server::update_clients(Some(message.to_json()), message.to_event());
+}
+
+// replayed events are held back and redacted just like live ones
+// the player only releases what it showed, so those still go out to let go of the key
+pub fn send_replayed(message: KeyMessage) {
+    if message.is_down && self::is_paused() {
+        return;
+    }
+
+    let redact = REDACT.load(Ordering::Relaxed);
+
+    let message = KeyMessage {
+        key: if redact { None } else { message.key },
+        id: if redact { None } else { message.id },
+        label: self::shown_label(message.label),
+        ..message
+    };
+
+    server::update_clients(Some(message.to_json()), message.to_event());
+}
+
+// shows the event to clients, returns the index and label of every binding it triggered
+// or None while paused, when nothing about the event should be kept
+fn dispatch(event: InputEvent) -> anyhow::Result<Option<Vec<RecordBinding>>> {
+    // held until the messages are out, so clients see transitions in the order they happened
+    let mut state = STATE.lock().unwrap();
+
+    let modifiers = state.track_modifiers(&event.input, event.is_down);
+
+    // modifiers are captured on release, which still has to let go of a binding on them
+    if let Input::Key(key) = &event.input {
+        let binding_mode = state.binding_mode();
+
+        if self::capture(
+            *key,
+            event.scancode,
+            event.is_down,
+            modifiers.clone(),
+            binding_mode,
+        ) && event.is_down
+        {
+            return Ok(Some(vec![]));
+        }
+    }
+
+    let paused = self::is_paused();
+
+    let (transitions, filtered, catch_all) = match state.handle(&event, &modifiers) {
+        Outcome::Held => return Ok(Some(vec![])),
+
+        // only the pause hotkey does anything while paused
+        Outcome::Control(control) if paused && control != Control::Pause => return Ok(None),
+
+        Outcome::Control(control) => {
+            drop(state);
+            self::run_control(control);
+
+            return Ok(Some(vec![]));
+        }
+
+        // the state still follows along, it's all let go silently on resume
+        Outcome::Bindings { .. } if paused => return Ok(None),
+
+        Outcome::Bindings {
+            transitions,
+            filtered,
+            catch_all,
+        } => (transitions, filtered, catch_all),
+    };
+
+    if let (true, true, Input::Key(key)) = (
+        CAPTURE_ALL.load(Ordering::Relaxed),
+        event.is_down,
+        &event.input,
+    ) {
+        let redact = REDACT.load(Ordering::Relaxed);
+        let mut typed_text = TYPED_TEXT.lock().unwrap();
+
+        if let Some(display) = typed_text.press(key, &modifiers, Instant::now(), redact) {
+            server::update_clients(None, display.to_event());
+        }
+    }
+
+    let keys = state.keys();
+
+    for i in filtered {
+        *FILTERED
+            .write()
+            .unwrap()
+            .entry(keys[i].bind.serialize())
+            .or_insert(0) += 1;
+    }
+
+    let mut triggered: Vec<RecordBinding> = vec![];
+    let mut counted: Vec<String> = vec![];
+    let mut pressed: Vec<usize> = vec![];
+
+    for transition in transitions {
+        let entry = &keys[transition.index];
+
+        let counter_name = entry.bind.serialize();
+        let mut counters = COUNTERS.write().unwrap();
+        let count = counters.entry(counter_name.clone()).or_insert(0);
+
+        if transition.is_down {
+            pressed.push(transition.index);
+
+            // two bindings on the same key share a counter, only count the press once
+            if !counted.contains(&counter_name) {
+                *count += 1;
+                counted.push(counter_name.clone());
+
+                COUNTERS_DIRTY.store(true, Ordering::Relaxed);
+            }
+        }
+
+        let count = *count;
+        drop(counters);
+
+        let key = entry.bind.serialize();
+        let label = self::label(entry);
+        let device = event.device.as_ref();
+        self::send_transition(
+            &entry.id, &key, &label, transition, count, event.time, device,
+        );
+
+        triggered.push(RecordBinding {
+            index: transition.index as u32,
+            id: entry.id.clone(),
+            label,
+        });
+    }
+
+    if let Some((transition, label)) = catch_all {
+        let mut counters = COUNTERS.write().unwrap();
+        let count = counters.entry(CATCH_ALL_COUNTER.to_string()).or_insert(0);
+
+        if transition.is_down {
+            *count += 1;
+            COUNTERS_DIRTY.store(true, Ordering::Relaxed);
+        }
+
+        let count = *count;
+        drop(counters);
+
+        let device = event.device.as_ref();
+        self::send_transition(
+            CATCH_ALL_COUNTER,
+            CATCH_ALL_COUNTER,
+            &label,
+            transition,
+            count,
+            event.time,
+            device,
+        );
+
+        triggered.push(RecordBinding {
+            index: transition.index as u32,
+            id: CATCH_ALL_COUNTER.to_string(),
+            label,
+        });
+    }
+
+    if !pressed.is_empty() {
+        RATES.lock().unwrap().push(&event.time, pressed);
+    }
+
+    Ok(Some(triggered))
+}
+
+fn probe_scancode(input: &Input) -> Option<u32> {
+    match input {
+        Input::Key(key) => helper::key_to_scancode(key),
+        _ => None,
+    }
+}
+
+fn count_of(counter_name: &str) -> u64 {
+    COUNTERS
+        .read()
+        .unwrap()
+        .get(counter_name)
+        .copied()
+        .unwrap_or(0)
+}
+
+// sends a release for a binding clients would otherwise keep showing as held
+// tells clients every held binding and unbound key was let go
+fn release_everything(state: &mut KeyboardState, time: Timestamp) {
+    for (i, _) in state.held() {
+        self::release_held(state, i, time);
+    }
+
+    let count = self::count_of(CATCH_ALL_COUNTER);
+
+    for (transition, label) in state.release_catch_all(&time) {
+        self::send_transition(
+            CATCH_ALL_COUNTER,
+            CATCH_ALL_COUNTER,
+            &label,
+            transition,
+            count,
+            time,
+            None,
+        );
+    }
+}
+
+fn release_held(state: &mut KeyboardState, i: usize, time: Timestamp) {
+    let transition = match state.force_release(i, &time) {
+        Some(transition) => transition,
+        None => return, // the real release got here first
+    };
+
+    let entry = &state.keys()[i];
+
+    let input = match entry.bind.input() {
+        Some(input) => input,
+        None => return,
+    };
+
+    let key = entry.bind.serialize();
+    let count = self::count_of(&key);
+
+    let label = self::label(entry);
+    self::send_transition(&entry.id, &key, &label, transition, count, time, None);
+
+    RECORDER.lock().unwrap().push(Record {
+        input: input.serialize(),
+        is_down: false,
+        unix_ms: time.unix_ms(),
+        monotonic_ns: time.monotonic_ns,
+        binding: Some(RecordBinding {
+            index: i as u32,
+            id: entry.id.clone(),
+            label,
+        }),
+    });
+}
+
+// what stuck keys are checked against, the timeout is all there is without one
+pub fn set_probe(probe: Option<Arc<dyn KeyProbe>>) {
+    *PROBE.write().unwrap() = probe;
+}
+
+// lets go of bindings the backend says are up, or that outlasted the timeout when it can't say
+fn check_stuck() {
+    let timeout_ns = *STUCK_TIMEOUT_MS.read().unwrap() * 1_000_000;
+    let probe = PROBE.read().unwrap().clone();
+
+    let now = Timestamp::new(SystemTime::now());
+    let mut state = STATE.lock().unwrap();
+
+    for (i, pressed_at) in state.held() {
+        let input = match state.keys().get(i).and_then(|entry| entry.bind.input()) {
+            Some(input) => input,
+            None => continue,
+        };
+
+        let scancode = self::probe_scancode(&input);
+
+        let is_stuck = match probe.as_ref().and_then(|p| p.is_held(&input, scancode)) {
+            Some(is_held) => !is_held,
+            None => {
+                let held_for = now.monotonic_ns.saturating_sub(pressed_at.monotonic_ns);
+                timeout_ns > 0 && held_for > timeout_ns
+            }
+        };
+
+        if is_stuck {
+            self::release_held(&mut state, i, now);
+        }
+    }
+
+    // stuck modifiers would stop every modified bind from matching
+    if let Some(probe) = probe {
+        state.retain_modifiers(|key| {
+            let input = Input::Key(*key);
+            probe.is_held(&input, self::probe_scancode(&input)) != Some(false)
+        });
+    }
+}
+
+// every held binding, so clients can fix up whatever they missed
+fn held_keys() -> Vec<HeldKey> {
+    let state = STATE.lock().unwrap();
+    let redact = REDACT.load(Ordering::Relaxed);
+
+    // what's held while paused was never shown
+    if self::is_paused() {
+        return vec![];
+    }
+
+    state
+        .held()
+        .into_iter()
+        .filter_map(|(i, pressed_at)| {
+            let entry = state.keys().get(i)?;
+            entry.bind.input()?;
+
+            let key = entry.bind.serialize();
+
+            Some(HeldKey {
+                index: i,
+                key: if redact { None } else { Some(key) },
+                id: if redact { None } else { Some(entry.id.clone()) },
+                label: self::shown_label(&self::label(entry)).to_string(),
+                since_ms: pressed_at.unix_ms(),
+            })
+        })
+        .collect()
+}
+
+pub fn held_payload() -> serde_json::Value {
+    serde_json::json!({ "held": self::held_keys() })
+}
+
+pub fn held_event() -> Event {
+    Event::now(EventKind::Held).with_payload(self::held_payload())
+}
+
+// checks for stuck keys straight away and sends clients what's held
+pub fn resync() -> serde_json::Value {
+    self::check_stuck();
+
+    server::update_clients(None, self::held_event());
+
+    self::held_payload()
+}
+
+fn current_rates(keys: &[KeyEntry]) -> Rates {
+    let now = Timestamp::new(SystemTime::now());
+    let mut rates = RATES.lock().unwrap().rates(&now, keys.len());
+
+    let filtered = FILTERED.read().unwrap();
+    rates.filtered = keys
+        .iter()
+        .map(|entry| filtered.get(&entry.bind.serialize()).copied().unwrap_or(0))
+        .collect();
+
+    rates
+}
+
+fn binding_states(keys: &[KeyEntry]) -> Vec<BindingState> {
+    let redact = REDACT.load(Ordering::Relaxed);
+
+    keys.iter()
+        .enumerate()
+        .map(|(i, entry)| {
+            let key = entry.bind.serialize();
+
+            BindingState {
+                index: i,
+                count: self::count_of(&key),
+                key: if redact { None } else { Some(key.clone()) },
+                id: if redact { None } else { Some(entry.id.clone()) },
+                bind: if redact { None } else { Some(key) },
+                mask: if redact { None } else { entry.mask.clone() },
+                label: self::shown_label(&self::label(entry)).to_string(),
+            }
+        })
+        .collect()
+}
+
+// a guard taken inside a struct literal lives until the end of it, this one drops here
+fn has_catch_all() -> bool {
+    STATE.lock().unwrap().catch_all()
+}
+
+pub fn config() -> Config {
+    Config {
+        bindings: self::binding_states(&self::keys()),
+        catch_all: self::has_catch_all(),
+    }
+}
+
+pub fn bindings() -> Vec<BindingState> {
+    self::binding_states(&self::keys())
+}
+
+// press counts by binding id, the catch-all slot's is under its own key
+pub fn counters() -> BTreeMap<String, u64> {
+    let mut counters: BTreeMap<String, u64> = self::keys()
+        .iter()
+        .map(|entry| (entry.id.clone(), self::count_of(&entry.bind.serialize())))
+        .collect();
+
+    if self::has_catch_all() {
+        counters.insert(
+            CATCH_ALL_COUNTER.to_string(),
+            self::count_of(CATCH_ALL_COUNTER),
+        );
+    }
+
+    counters
+}
+
+// tells clients the binding list changed, nothing is held afterwards
+// so presets can rebuild their layout from scratch
+pub fn broadcast_config() {
+    let event = Event::now(EventKind::Config).with_payload(self::config());
+    server::update_clients(None, event);
+}
+
+// everything a client needs to draw the overlay as it is right now
+pub fn snapshot() -> Snapshot {
+    let keys = self::keys();
+    let bindings = self::binding_states(&keys);
+
+    Snapshot {
+        version: protocol::VERSION,
+        bindings,
+        catch_all: self::has_catch_all(),
+        held: self::held_keys(),
+        paused: self::is_paused(),
+        reason: self::pause_reason(),
+        rates: self::current_rates(&keys),
+    }
+}
+
+// held bindings as key presses in the original array format, for older presets
+pub fn held_messages() -> Vec<String> {
+    let state = STATE.lock().unwrap();
+    let redact = REDACT.load(Ordering::Relaxed);
+
+    if self::is_paused() {
+        return vec![];
+    }
+
+    state
+        .held()
+        .into_iter()
+        .filter_map(|(i, pressed_at)| {
+            let entry = state.keys().get(i)?;
+            let key = entry.bind.serialize();
+            let label = self::label(entry);
+
+            let message = KeyMessage {
+                key: if redact { None } else { Some(&key) },
+                id: if redact { None } else { Some(&entry.id) },
+                label: self::shown_label(&label),
+                is_down: true,
+                index: i,
+                count: self::count_of(&key),
+                time: pressed_at,
+                hold_ns: None,
+                device: None,
+            };
+
+            Some(message.to_json())
+        })
+        .collect()
+}
+
+// only event clients are sent rates, older presets expect nothing but presses and "reset"
+fn broadcast_rates() {
+    let mut last_sent: Option<Rates> = None;
+    let mut ticks_since_sent = 0;
+
+    loop {
+        thread::sleep(RATES_TICK);
+
+        if self::is_paused() {
+            continue;
+        }
+
+        let rates = self::current_rates(&self::keys()).rounded();
+
+        ticks_since_sent += 1;
+
+        if last_sent.as_ref() != Some(&rates) || ticks_since_sent >= RATES_HEARTBEAT {
+            let event = Event::now(EventKind::Rates).with_payload(&rates);
+            server::update_clients(None, event);
+
+            last_sent = Some(rates);
+            ticks_since_sent = 0;
+        }
+    }
+}
+
 pub fn start(settings: OverlaySettings) -> anyhow::Result<()> {
-    self::refresh_keys(settings.keys, settings.reset)?;
+    self::refresh_keys(&settings)?;
+    self::refresh_recording(&settings.recording);
+    self::refresh_privacy(&settings.privacy);
 
-    let (sender, receiver): (Sender<String>, Receiver<String>) = mpsc::channel();
-    thread::spawn(move || {
-        let sender_clone = sender.clone();
+    // counts aren't worth going without input over, a bad file is treated like a missing one
+    if let Err(err) = self::load_counters() {
+        eprintln!("starting with no counts, {:?}", err);
+    }
 
-        let closure = move |event: Event| {
-            if let Err(err) = || -> anyhow::Result<()> {
-                match event.event_type {
-                    EventType::KeyPress(rdev_key) => {
-                        on_key_interact(rdev_key, true)?;
-                    }
+    let binding_mode = settings.toml_settings.keyboard.binding_mode;
+    STATE.lock().unwrap().set_binding_mode(binding_mode);
 
-                    EventType::KeyRelease(rdev_key) => {
-                        on_key_interact(rdev_key, false)?;
-                    }
+    let kps_window = Duration::from_millis(settings.toml_settings.keyboard.kps_window_ms.max(1));
+    RATES.lock().unwrap().set_window(kps_window);
 
-                    _ => (),
-                }
+    thread::spawn(self::broadcast_rates);
+
+    thread::spawn(|| loop {
+        thread::sleep(COUNTERS_SAVE_INTERVAL);
+
+        if COUNTERS_DIRTY.swap(false, Ordering::Relaxed) {
+            let _ = self::save_counters();
+        }
+    });
+
+    thread::spawn(|| loop {
+        thread::sleep(RECORDING_FLUSH_INTERVAL);
+
+        let mut recorder = RECORDER.lock().unwrap();
+
+        if let Some((path, bytes)) = recorder.take_pending() {
+            self::queue_recording_job(RecordingJob::Write(path, bytes));
+        }
+    });
+
+    let source = backends::from_settings(&settings.input)?;
+    self::set_probe(source.probe());
+
+    thread::spawn(self::watch_focus);
+
+    thread::spawn(|| loop {
+        thread::sleep(STUCK_CHECK_INTERVAL);
+        self::check_stuck();
+    });
+
+    let (sender, receiver): (Sender<String>, Receiver<String>) = mpsc::channel();
+    thread::spawn(move || {
+        let sender_clone = sender.clone();
 
-                Ok(())
-            }() {
+        let sink = move |event: InputEvent| {
+            if let Err(err) = self::on_key_interact(event) {
                 sender_clone.send(format!("{:?}", err)).unwrap();
             }
         };
 
-        if let Err(err) = rdev::listen(closure) {
+        if let Err(err) = source.listen(Box::new(sink)) {
             sender.send(format!("{:?}", err)).unwrap();
         }
     });
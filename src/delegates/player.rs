@@ -0,0 +1,412 @@
+extern crate lazy_static;
+
+use std::collections::HashMap;
+use std::sync::{Condvar, Mutex, RwLock};
+use std::time::{Duration, Instant, SystemTime};
+
+use lazy_static::lazy_static;
+
+use crate::{
+    input::{Bind, Timestamp},
+    message::KeyMessage,
+    osu::{self, OsuBinding},
+    record::{self, Record, RecordBinding},
+    settings::{KeyEntry, OsuSettings, OverlaySettings},
+};
+
+use super::keyboard;
+
+lazy_static! {
+    static ref PLAYBACK: Mutex<Playback> = Mutex::new(Playback::new());
+    // woken whenever playback is controlled, so waits for the next record can be cut short
+    static ref WAKE: Condvar = Condvar::new();
+
+    static ref OSU_KEYS: RwLock<OsuSettings> = RwLock::new(OsuSettings::default());
+}
+
+// looping restarts after this, so a recording of simultaneous events can't spin
+const LOOP_GAP: Duration = Duration::from_millis(500);
+const MIN_SPEED: f64 = 0.01;
+const MAX_SPEED: f64 = 100_f64;
+
+struct Playback {
+    records: Vec<Record>, // only the ones that triggered a binding
+    position: usize,      // next record to play
+
+    playing: bool,
+    looping: bool,
+    speed: f64,
+
+    // how far into the recording playback is, as of clock_at when playing
+    clock_ns: u64,
+    clock_at: Option<Instant>,
+
+    // set while a loop waits out its gap, playback picks up again at the start after it
+    resume_at: Option<Instant>,
+
+    counts: HashMap<u32, u64>,
+    held: HashMap<u32, (u64, RecordBinding)>, // press time and binding by index
+}
+
+impl Playback {
+    fn new() -> Self {
+        Self {
+            records: vec![],
+            position: 0,
+
+            playing: false,
+            looping: false,
+            speed: 1_f64,
+
+            clock_ns: 0,
+            clock_at: None,
+
+            resume_at: None,
+
+            counts: HashMap::new(),
+            held: HashMap::new(),
+        }
+    }
+
+    fn offset(&self, i: usize) -> u64 {
+        let start = self.records.first().map_or(0, |record| record.monotonic_ns);
+        self.records[i].monotonic_ns.saturating_sub(start)
+    }
+
+    fn length_ns(&self) -> u64 {
+        match self.records.len() {
+            0 => 0,
+            len => self.offset(len - 1),
+        }
+    }
+
+    fn clock(&self) -> u64 {
+        match self.clock_at {
+            Some(clock_at) => {
+                let elapsed = clock_at.elapsed().as_nanos() as f64 * self.speed;
+                self.clock_ns.saturating_add(elapsed as u64)
+            }
+
+            None => self.clock_ns,
+        }
+    }
+
+    fn load(&mut self, records: Vec<Record>) {
+        self.release_all();
+
+        *self = Self {
+            records,
+            looping: self.looping,
+            speed: self.speed,
+            ..Self::new()
+        };
+    }
+
+    fn play(&mut self) {
+        if self.position >= self.records.len() {
+            self.seek(0);
+        }
+
+        self.clock_at = Some(Instant::now());
+        self.playing = true;
+        self.resume_at = None;
+    }
+
+    fn pause(&mut self) {
+        self.clock_ns = self.clock();
+        self.clock_at = None;
+        self.playing = false;
+        self.resume_at = None;
+    }
+
+    fn set_speed(&mut self, speed: f64) {
+        self.clock_ns = self.clock();
+
+        if self.playing {
+            self.clock_at = Some(Instant::now());
+        }
+
+        self.speed = speed.clamp(MIN_SPEED, MAX_SPEED);
+    }
+
+    fn seek(&mut self, target_ns: u64) {
+        self.release_all();
+
+        let position = (0..self.records.len())
+            .find(|i| self.offset(*i) >= target_ns)
+            .unwrap_or(self.records.len());
+
+        // counts carry on from wherever the seek lands
+        self.counts.clear();
+
+        for record in &self.records[..position] {
+            if let (true, Some(binding)) = (record.is_down, &record.binding) {
+                *self.counts.entry(binding.index).or_insert(0) += 1;
+            }
+        }
+
+        self.position = position;
+        self.clock_ns = target_ns;
+
+        if self.playing {
+            self.clock_at = Some(Instant::now());
+        }
+    }
+
+    fn step(&mut self) {
+        self.pause();
+
+        if self.position < self.records.len() {
+            self.clock_ns = self.offset(self.position);
+            self.emit(self.position);
+            self.position += 1;
+        }
+    }
+
+    // let go of everything so clients don't keep keys down after a seek or stop
+    fn release_all(&mut self) {
+        let time = Timestamp::new(SystemTime::now());
+
+        for (index, (_, binding)) in self.held.drain() {
+            let key = keyboard::bind_of(&binding.id);
+
+            let message = KeyMessage {
+                key: key.as_deref(),
+                id: Some(&binding.id),
+                label: &binding.label,
+                is_down: false,
+                index: index as usize,
+                count: self.counts.get(&index).copied().unwrap_or(0),
+                time,
+                hold_ns: None,
+                device: None,
+            };
+
+            keyboard::send_replayed(message);
+        }
+    }
+
+    fn emit(&mut self, i: usize) {
+        let record = &self.records[i];
+
+        let binding = match &record.binding {
+            Some(binding) => binding.clone(),
+            None => return,
+        };
+
+        let index = binding.index;
+
+        let count = self.counts.entry(index).or_insert(0);
+        let mut hold_ns = None;
+
+        if record.is_down {
+            *count += 1;
+
+            // presses aren't shown while paused, so neither are their releases
+            if keyboard::is_paused() {
+                return;
+            }
+
+            self.held
+                .insert(index, (record.monotonic_ns, binding.clone()));
+        } else {
+            match self.held.remove(&index) {
+                Some((pressed_at, _)) => {
+                    hold_ns = Some(record.monotonic_ns.saturating_sub(pressed_at))
+                }
+                None => return,
+            }
+        }
+
+        let key = keyboard::bind_of(&binding.id);
+
+        // replayed events happen now as far as clients are concerned
+        let message = KeyMessage {
+            key: key.as_deref(),
+            id: Some(&binding.id),
+            label: &binding.label,
+            is_down: record.is_down,
+            index: index as usize,
+            count: *count,
+            time: Timestamp::new(SystemTime::now()),
+            hold_ns,
+            device: None,
+        };
+
+        keyboard::send_replayed(message);
+    }
+
+    // plays everything that's due, returns how long until the next record
+    fn advance(&mut self) -> Option<Duration> {
+        if let Some(resume_at) = self.resume_at {
+            let now = Instant::now();
+
+            if now < resume_at {
+                return Some(resume_at - now);
+            }
+
+            self.play();
+        }
+
+        if !self.playing {
+            return None;
+        }
+
+        loop {
+            if self.position >= self.records.len() {
+                // the clock stays at the start through the gap, or the start would all play at once
+                if self.looping && !self.records.is_empty() {
+                    self.pause();
+                    self.seek(0);
+                    self.resume_at = Some(Instant::now() + LOOP_GAP);
+
+                    return Some(LOOP_GAP);
+                }
+
+                self.pause();
+                return None;
+            }
+
+            let due = self.offset(self.position);
+            let clock = self.clock();
+
+            if due > clock {
+                let wait = (due - clock) as f64 / self.speed;
+                return Some(Duration::from_nanos(wait as u64));
+            }
+
+            self.emit(self.position);
+            self.position += 1;
+        }
+    }
+
+    fn status(&self) -> serde_json::Value {
+        serde_json::json!({
+            "loaded": !self.records.is_empty(),
+            "playing": self.playing || self.resume_at.is_some(),
+            "looping": self.looping,
+            "speed": self.speed,
+            "position_ms": self.clock().min(self.length_ns()) as f64 / 1_000_000_f64,
+            "length_ms": self.length_ns() as f64 / 1_000_000_f64,
+        })
+    }
+}
+
+fn control<T>(action: impl FnOnce(&mut Playback) -> T) -> T {
+    let result = action(&mut PLAYBACK.lock().unwrap());
+    WAKE.notify_all();
+
+    result
+}
+
+pub fn refresh(settings: &OverlaySettings) {
+    *OSU_KEYS.write().unwrap() = settings.osu.clone();
+}
+
+// the first configured binding on the same input as the osu! key's bind
+fn osu_binding(bind: &str, keys: &[KeyEntry]) -> Option<OsuBinding> {
+    let input = Bind::deserialize(bind).ok()?.input()?;
+
+    keys.iter().enumerate().find_map(|(i, entry)| {
+        if entry.bind.input().as_ref() != Some(&input) {
+            return None;
+        }
+
+        Some(OsuBinding {
+            input: input.serialize(),
+            index: i as u32,
+            id: entry.id.clone(),
+            label: entry.mask.clone().unwrap_or_else(|| input.serialize()),
+        })
+    })
+}
+
+fn load_osu(path: &str) -> anyhow::Result<Vec<Record>> {
+    let frames = osu::read_file(path)?;
+
+    let osu_keys = OSU_KEYS.read().unwrap().clone();
+    let keys = keyboard::keys();
+
+    // in OsuKey::ALL order
+    let bindings = [&osu_keys.m1, &osu_keys.m2, &osu_keys.k1, &osu_keys.k2]
+        .map(|bind| self::osu_binding(bind, &keys));
+
+    Ok(osu::to_records(&frames, &bindings))
+}
+
+// osu! replays are told apart by their extension, anything else is a recording
+pub fn load(path: &str) -> anyhow::Result<()> {
+    let is_osu = std::path::Path::new(path)
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("osr"));
+
+    let records = if is_osu {
+        self::load_osu(path)?
+    } else {
+        record::read_file(path)?
+    };
+
+    let records: Vec<Record> = records
+        .into_iter()
+        .filter(|record| record.binding.is_some())
+        .collect();
+
+    self::control(|playback| playback.load(records));
+
+    Ok(())
+}
+
+pub fn play() {
+    self::control(Playback::play);
+}
+
+pub fn pause() {
+    self::control(Playback::pause);
+}
+
+pub fn stop() {
+    self::control(|playback| {
+        playback.pause();
+        playback.seek(0);
+    });
+}
+
+pub fn step() {
+    self::control(Playback::step);
+}
+
+pub fn seek(position_ms: f64) {
+    let target_ns = (position_ms.max(0_f64) * 1_000_000_f64) as u64;
+    self::control(|playback| playback.seek(target_ns));
+}
+
+pub fn set_speed(speed: f64) -> anyhow::Result<()> {
+    if !speed.is_finite() || speed <= 0_f64 {
+        anyhow::bail!("Speed must be a positive number");
+    }
+
+    self::control(|playback| playback.set_speed(speed));
+
+    Ok(())
+}
+
+pub fn set_looping(looping: bool) {
+    self::control(|playback| playback.looping = looping);
+}
+
+pub fn status() -> serde_json::Value {
+    PLAYBACK.lock().unwrap().status()
+}
+
+pub fn start(settings: OverlaySettings) -> anyhow::Result<()> {
+    self::refresh(&settings);
+
+    let mut playback = PLAYBACK.lock().unwrap();
+
+    loop {
+        playback = match playback.advance() {
+            Some(wait) => WAKE.wait_timeout(playback, wait).unwrap().0,
+            None => WAKE.wait(playback).unwrap(),
+        };
+    }
+}
@@ -1,41 +1,366 @@
 extern crate anyhow;
-extern crate futures;
 extern crate futures_util;
 extern crate lazy_static;
 extern crate poem;
+extern crate serde_json;
 extern crate tokio;
 
-use std::sync::{Arc, Mutex};
-
 use lazy_static::lazy_static;
 use poem::{
     endpoint::StaticFilesEndpoint,
     handler,
+    http::StatusCode,
     listener::TcpListener,
-    web::websocket::{Message, WebSocket, WebSocketStream},
+    web::{
+        websocket::{Message, WebSocket},
+        Json, Query,
+    },
     IntoResponse, Route, Server,
 };
 
-use futures_util::SinkExt;
+use futures_util::{SinkExt, StreamExt};
+use serde::Deserialize;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use tokio::sync::{broadcast, mpsc};
+
+use crate::{
+    protocol::{self, Event, EventKind},
+    settings::OverlaySettings,
+};
+
+use super::{keyboard, player};
+
+// how many messages a client can fall behind before it's dropped
+const CLIENT_QUEUE: usize = 256;
 
-use crate::settings::OverlaySettings;
+// websocket clients on either endpoint
+static CLIENTS: AtomicUsize = AtomicUsize::new(0);
+
+// which endpoint a client connected to
+#[derive(Clone, Copy)]
+enum Protocol {
+    Legacy,
+    Events,
+}
+
+// every message in both formats, each client is sent the one it asked for
+struct Broadcast {
+    legacy: Option<String>, // only presses and "reset", older presets index into arrays
+    event: String,
+}
 
 lazy_static! {
-    pub static ref CLIENT_LIST: Arc<Mutex<Vec<WebSocketStream>>> = Arc::new(Mutex::new(Vec::new()));
+    // every client reads its own copy of this at its own pace
+    static ref BROADCAST: broadcast::Sender<Arc<Broadcast>> = broadcast::channel(CLIENT_QUEUE).0;
+}
+
+// never waits on clients, so it's safe to call from the input hook
+pub fn update_clients(legacy: Option<String>, event: Event) {
+    let broadcast = Broadcast {
+        legacy,
+        event: event.to_json(),
+    };
+
+    // only fails when nobody is connected
+    let _ = BROADCAST.send(Arc::new(broadcast));
 }
 
-pub fn update_clients(data: String) {
-    CLIENT_LIST.lock().unwrap().retain_mut(|socket| {
-        let result = futures::executor::block_on(socket.send(Message::Text(data.clone())));
-        return result.is_ok();
-    });
+// what a client is sent as soon as it connects, so it can draw before the next event
+fn hello(protocol: Protocol) -> Vec<String> {
+    match protocol {
+        Protocol::Events => {
+            vec![Event::now(EventKind::Snapshot)
+                .with_payload(keyboard::snapshot())
+                .to_json()]
+        }
+
+        // older presets only understand presses, so held keys are pressed again for them
+        Protocol::Legacy => keyboard::held_messages(),
+    }
+}
+
+// sends broadcasts and replies to one client until it disconnects or falls too far behind
+async fn forward_broadcasts(
+    mut sink: impl SinkExt<Message> + Unpin,
+    protocol: Protocol,
+    mut replies: mpsc::UnboundedReceiver<String>,
+) {
+    // subscribed first so nothing between the snapshot and the first broadcast is missed
+    let mut receiver = BROADCAST.subscribe();
+
+    // reads keyboard state behind locks, keep it off the runtime
+    let hello = tokio::task::spawn_blocking(move || self::hello(protocol))
+        .await
+        .unwrap_or_default();
+
+    for data in hello {
+        if sink.send(Message::Text(data)).await.is_err() {
+            return;
+        }
+    }
+
+    loop {
+        let data = tokio::select! {
+            // a lagging client would only be shown stale keys, it can reconnect and resync instead
+            broadcast = receiver.recv() => match (broadcast, protocol) {
+                (Err(_), _) => break,
+                (Ok(broadcast), Protocol::Legacy) => match &broadcast.legacy {
+                    Some(legacy) => legacy.clone(),
+                    None => continue,
+                },
+                (Ok(broadcast), Protocol::Events) => broadcast.event.clone(),
+            },
+
+            reply = replies.recv() => match reply {
+                Some(reply) => reply,
+                None => break,
+            },
+        };
+
+        if sink.send(Message::Text(data)).await.is_err() {
+            return;
+        }
+    }
+
+    let _ = sink.close().await;
+}
+
+#[derive(Deserialize)]
+struct ClientRequest {
+    #[serde(rename = "type")]
+    kind: String,
+}
+
+// clients can send "resync", or {"type": "resync"} on the events endpoint,
+// to be told every held key again. returns what's sent back to that client alone
+fn on_client_message(message: &str, protocol: Protocol) -> Vec<String> {
+    let kind = match serde_json::from_str::<ClientRequest>(message) {
+        Ok(request) => request.kind,
+        Err(_) => message.trim().to_string(),
+    };
+
+    if kind != "resync" {
+        return vec![];
+    }
+
+    keyboard::resync();
+
+    // event clients are sent the held event, legacy ones only understand presses
+    match protocol {
+        Protocol::Legacy => keyboard::held_messages(),
+        Protocol::Events => vec![],
+    }
+}
+
+fn client(ws: WebSocket, protocol: Protocol) -> impl IntoResponse {
+    ws.on_upgrade(move |socket| async move {
+        CLIENTS.fetch_add(1, Ordering::Relaxed);
+
+        let (sink, mut stream) = socket.split();
+        let (reply_sender, replies) = mpsc::unbounded_channel();
+        let forward = tokio::spawn(self::forward_broadcasts(sink, protocol, replies));
+
+        while let Some(Ok(message)) = stream.next().await {
+            if let Message::Text(text) = message {
+                // waits on the keyboard state lock, keep it off the runtime
+                let handled =
+                    tokio::task::spawn_blocking(move || self::on_client_message(&text, protocol));
+
+                for reply in handled.await.unwrap_or_default() {
+                    let _ = reply_sender.send(reply);
+                }
+            }
+        }
+
+        forward.abort();
+        CLIENTS.fetch_sub(1, Ordering::Relaxed);
+    })
 }
 
 #[handler]
 async fn websocket_connect(ws: WebSocket) -> impl IntoResponse {
-    ws.on_upgrade(|mut socket| async move {
-        CLIENT_LIST.lock().unwrap().push(socket);
+    self::client(ws, Protocol::Legacy)
+}
+
+#[handler]
+async fn events_connect(ws: WebSocket) -> impl IntoResponse {
+    self::client(ws, Protocol::Events)
+}
+
+// for handlers that wait on locks or files, so they don't hold up the runtime
+async fn blocking<T: Send + 'static>(
+    handler: impl FnOnce() -> T + Send + 'static,
+) -> poem::Result<T> {
+    tokio::task::spawn_blocking(handler).await.map_err(|err| {
+        poem::Error::from_string(format!("{:?}", err), StatusCode::INTERNAL_SERVER_ERROR)
+    })
+}
+
+fn api_response(result: anyhow::Result<String>) -> (StatusCode, String) {
+    match result {
+        Ok(body) => (StatusCode::OK, body),
+        Err(err) => (StatusCode::INTERNAL_SERVER_ERROR, format!("{:?}", err)),
+    }
+}
+
+// recording endpoints answer with the path of the file they wrote to
+#[handler]
+async fn recording_start() -> poem::Result<impl IntoResponse> {
+    self::blocking(|| self::api_response(keyboard::start_recording())).await
+}
+
+#[handler]
+async fn recording_stop() -> poem::Result<impl IntoResponse> {
+    self::blocking(|| match keyboard::stop_recording() {
+        Ok(None) => (StatusCode::CONFLICT, "Not recording".to_string()),
+        result => self::api_response(result.map(|path| path.unwrap_or_default())),
+    })
+    .await
+}
+
+#[handler]
+async fn replay_save() -> poem::Result<impl IntoResponse> {
+    self::blocking(|| self::api_response(keyboard::save_replay())).await
+}
+
+#[handler]
+async fn resync() -> poem::Result<impl IntoResponse> {
+    self::blocking(|| Json(keyboard::resync())).await
+}
+
+// read-only state, in the same shapes the snapshot uses
+#[handler]
+async fn state_snapshot() -> poem::Result<impl IntoResponse> {
+    self::blocking(|| Json(keyboard::snapshot())).await
+}
+
+#[handler]
+async fn state_config() -> poem::Result<impl IntoResponse> {
+    self::blocking(|| Json(keyboard::config())).await
+}
+
+#[handler]
+async fn state_bindings() -> poem::Result<impl IntoResponse> {
+    self::blocking(|| Json(keyboard::bindings())).await
+}
+
+#[handler]
+async fn state_held() -> poem::Result<impl IntoResponse> {
+    self::blocking(|| Json(keyboard::held_payload())).await
+}
+
+#[handler]
+async fn state_counters() -> poem::Result<impl IntoResponse> {
+    self::blocking(|| Json(keyboard::counters())).await
+}
+
+#[handler]
+fn server_info() -> impl IntoResponse {
+    Json(serde_json::json!({
+        "name": env!("CARGO_PKG_NAME"),
+        "version": env!("CARGO_PKG_VERSION"),
+        "protocol": protocol::VERSION,
+        "clients": CLIENTS.load(Ordering::Relaxed),
+    }))
+}
+
+#[derive(Deserialize)]
+struct PathQuery {
+    path: String,
+}
+
+#[derive(Deserialize)]
+struct SeekQuery {
+    ms: f64,
+}
+
+#[derive(Deserialize)]
+struct SpeedQuery {
+    value: f64,
+}
+
+#[derive(Deserialize)]
+struct LoopQuery {
+    enabled: bool,
+}
+
+// playback endpoints answer with the playback status
+#[handler]
+async fn playback_status() -> poem::Result<impl IntoResponse> {
+    self::blocking(|| Json(player::status())).await
+}
+
+#[handler]
+async fn playback_load(Query(query): Query<PathQuery>) -> poem::Result<impl IntoResponse> {
+    self::blocking(move || match player::load(&query.path) {
+        Ok(_) => Json(player::status()).into_response(),
+        Err(err) => self::api_response(Err(err)).into_response(),
+    })
+    .await
+}
+
+#[handler]
+async fn playback_play() -> poem::Result<impl IntoResponse> {
+    self::blocking(|| {
+        player::play();
+        Json(player::status())
+    })
+    .await
+}
+
+#[handler]
+async fn playback_pause() -> poem::Result<impl IntoResponse> {
+    self::blocking(|| {
+        player::pause();
+        Json(player::status())
     })
+    .await
+}
+
+#[handler]
+async fn playback_stop() -> poem::Result<impl IntoResponse> {
+    self::blocking(|| {
+        player::stop();
+        Json(player::status())
+    })
+    .await
+}
+
+#[handler]
+async fn playback_step() -> poem::Result<impl IntoResponse> {
+    self::blocking(|| {
+        player::step();
+        Json(player::status())
+    })
+    .await
+}
+
+#[handler]
+async fn playback_seek(Query(query): Query<SeekQuery>) -> poem::Result<impl IntoResponse> {
+    self::blocking(move || {
+        player::seek(query.ms);
+        Json(player::status())
+    })
+    .await
+}
+
+#[handler]
+async fn playback_speed(Query(query): Query<SpeedQuery>) -> poem::Result<impl IntoResponse> {
+    self::blocking(move || match player::set_speed(query.value) {
+        Ok(()) => Json(player::status()).into_response(),
+        Err(err) => (StatusCode::BAD_REQUEST, err.to_string()).into_response(),
+    })
+    .await
+}
+
+#[handler]
+async fn playback_loop(Query(query): Query<LoopQuery>) -> poem::Result<impl IntoResponse> {
+    self::blocking(move || {
+        player::set_looping(query.enabled);
+        Json(player::status())
+    })
+    .await
 }
 
 #[tokio::main]
@@ -49,10 +374,32 @@ pub async fn start(settings: OverlaySettings) -> anyhow::Result<()> {
         .redirect_to_slash_directory()
         .index_file("index.html");
 
-    let app = Route::new().nest("/", file_endpoint).at(
-        settings.web.websocket_endpoint,
-        poem::get(websocket_connect),
-    );
+    let app = Route::new()
+        .nest("/", file_endpoint)
+        .at(
+            settings.web.websocket_endpoint,
+            poem::get(websocket_connect),
+        )
+        .at(settings.web.events_endpoint, poem::get(events_connect))
+        .at("/api/info", poem::get(server_info))
+        .at("/api/snapshot", poem::get(state_snapshot))
+        .at("/api/config", poem::get(state_config))
+        .at("/api/bindings", poem::get(state_bindings))
+        .at("/api/held", poem::get(state_held))
+        .at("/api/counters", poem::get(state_counters))
+        .at("/api/recording/start", poem::post(recording_start))
+        .at("/api/recording/stop", poem::post(recording_stop))
+        .at("/api/replay/save", poem::post(replay_save))
+        .at("/api/resync", poem::post(resync))
+        .at("/api/playback", poem::get(playback_status))
+        .at("/api/playback/load", poem::post(playback_load))
+        .at("/api/playback/play", poem::post(playback_play))
+        .at("/api/playback/pause", poem::post(playback_pause))
+        .at("/api/playback/stop", poem::post(playback_stop))
+        .at("/api/playback/step", poem::post(playback_step))
+        .at("/api/playback/seek", poem::post(playback_seek))
+        .at("/api/playback/speed", poem::post(playback_speed))
+        .at("/api/playback/loop", poem::post(playback_loop));
 
     if let Err(error) = Server::new(TcpListener::bind(address)).run(app).await {
         anyhow::bail!("{:?}", error);
@@ -60,3 +407,167 @@ pub async fn start(settings: OverlaySettings) -> anyhow::Result<()> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+    use std::time::SystemTime;
+
+    use super::*;
+    use crate::{
+        backends::KeyProbe,
+        input::{Input, InputEvent, Timestamp},
+        key::Key,
+        settings::{KeySettings, Settings},
+    };
+
+    // the keyboard state is shared, these can't run alongside each other
+    static KEYBOARD: Mutex<()> = Mutex::new(());
+
+    // sets up the default bindings with Z held
+    fn hold_z() -> OverlaySettings {
+        let (settings, _) = OverlaySettings::from_toml(Settings::default()).unwrap();
+        keyboard::refresh_keys(&settings).unwrap();
+
+        let time = Timestamp::new(SystemTime::now());
+        let press = InputEvent::new(Input::Key(Key::Z), Some(44), true, time);
+        keyboard::on_key_interact(press).unwrap();
+
+        settings
+    }
+
+    #[test]
+    fn legacy_resync_sends_held_presses() {
+        let _keyboard = KEYBOARD.lock().unwrap();
+        self::hold_z();
+
+        let replies = self::on_client_message("resync", Protocol::Legacy);
+        assert_eq!(replies.len(), 1);
+
+        let held: serde_json::Value = serde_json::from_str(&replies[0]).unwrap();
+        assert_eq!(held[0], "Z");
+        assert_eq!(held[1], true);
+        assert_eq!(held[2], 0);
+
+        // event clients get the broadcast held event instead
+        assert!(self::on_client_message("{\"type\": \"resync\"}", Protocol::Events).is_empty());
+        assert!(self::on_client_message("hello", Protocol::Legacy).is_empty());
+    }
+
+    #[test]
+    fn saving_bindings_releases_held_keys() {
+        let _keyboard = KEYBOARD.lock().unwrap();
+        let settings = self::hold_z();
+
+        let mut receiver = BROADCAST.subscribe();
+        keyboard::refresh_keys(&settings).unwrap();
+
+        let mut legacy = vec![];
+
+        while let Ok(broadcast) = receiver.try_recv() {
+            legacy.extend(broadcast.legacy.clone());
+        }
+
+        assert_eq!(legacy.len(), 1);
+
+        let released: serde_json::Value = serde_json::from_str(&legacy[0]).unwrap();
+        assert_eq!(released[0], "Z");
+        assert_eq!(released[1], false);
+
+        assert!(keyboard::held_messages().is_empty());
+    }
+
+    #[test]
+    fn capturing_a_bound_modifier_releases_it() {
+        let _keyboard = KEYBOARD.lock().unwrap();
+
+        let mut toml_settings = Settings::default();
+        toml_settings.keyboard.keys = vec![KeySettings::Short("LShift".to_string())];
+
+        let (settings, _) = OverlaySettings::from_toml(toml_settings).unwrap();
+        keyboard::refresh_keys(&settings).unwrap();
+
+        let time = Timestamp::new(SystemTime::now());
+        let press = InputEvent::new(Input::Key(Key::LShift), Some(42), true, time);
+        let release = InputEvent::new(Input::Key(Key::LShift), Some(42), false, time);
+
+        keyboard::begin_capture();
+        keyboard::on_key_interact(press).unwrap();
+        assert_eq!(keyboard::held_messages().len(), 1);
+
+        keyboard::on_key_interact(release).unwrap();
+
+        let captured = keyboard::take_capture().unwrap();
+        assert_eq!(captured.key, Some(Key::LShift));
+        assert!(keyboard::held_messages().is_empty());
+    }
+
+    #[test]
+    fn key_events_carry_the_bind_and_the_id() {
+        let _keyboard = KEYBOARD.lock().unwrap();
+
+        let mut receiver = BROADCAST.subscribe();
+        let settings = self::hold_z();
+
+        let mut events = vec![];
+
+        while let Ok(broadcast) = receiver.try_recv() {
+            events.push(serde_json::from_str::<serde_json::Value>(&broadcast.event).unwrap());
+        }
+
+        let pressed = events.iter().find(|event| event["type"] == "key").unwrap();
+        assert_eq!(pressed["key"], "Z");
+        assert_eq!(pressed["id"], settings.keys[0].id.as_str());
+
+        let snapshot = serde_json::to_value(keyboard::snapshot()).unwrap();
+        assert_eq!(snapshot["bindings"][0]["key"], "Z");
+        assert_eq!(snapshot["bindings"][0]["id"], settings.keys[0].id.as_str());
+        assert_eq!(snapshot["bindings"][0]["bind"], "Z");
+        assert!(snapshot["bindings"][0]["mask"].is_null());
+        assert_eq!(snapshot["held"][0]["key"], "Z");
+    }
+
+    // a backend that says every key is up, or down
+    struct FixedProbe(bool);
+
+    impl KeyProbe for FixedProbe {
+        fn is_held(&self, _input: &Input, _scancode: Option<u32>) -> Option<bool> {
+            Some(self.0)
+        }
+    }
+
+    #[test]
+    fn default_settings_let_go_of_keys_the_backend_says_are_up() {
+        let _keyboard = KEYBOARD.lock().unwrap();
+
+        // the default timeout is off, only the backend's answer releases anything
+        assert_eq!(Settings::default().keyboard.stuck_timeout_ms, 0);
+        self::hold_z();
+
+        keyboard::set_probe(Some(Arc::new(FixedProbe(true))));
+        keyboard::resync();
+        assert_eq!(keyboard::held_messages().len(), 1);
+
+        keyboard::set_probe(Some(Arc::new(FixedProbe(false))));
+        keyboard::resync();
+        assert!(keyboard::held_messages().is_empty());
+
+        keyboard::set_probe(None);
+    }
+
+    #[test]
+    fn bindings_keep_the_bind_apart_from_the_mask() {
+        let _keyboard = KEYBOARD.lock().unwrap();
+
+        let mut toml_settings = Settings::default();
+        toml_settings.keyboard.keys = vec![KeySettings::Short("^Z:zed".to_string())];
+
+        let (settings, _) = OverlaySettings::from_toml(toml_settings).unwrap();
+        keyboard::refresh_keys(&settings).unwrap();
+
+        let bindings = serde_json::to_value(keyboard::bindings()).unwrap();
+        assert_eq!(bindings[0]["bind"], "^Z");
+        assert_eq!(bindings[0]["mask"], "zed");
+        assert_eq!(bindings[0]["label"], "zed");
+    }
+}
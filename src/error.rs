@@ -4,6 +4,9 @@ extern crate native_dialog;
 use native_dialog::{MessageDialog, MessageType};
 use std::fmt::Debug;
 
+use crate::delegates::keyboard;
+
+#[allow(clippy::upper_case_acronyms)]
 pub enum ErrorStatus {
     FAILURE = 1,
     SUCCESS = 0,
@@ -34,5 +37,7 @@ pub fn display_error<T: Debug>(thread_name: &str, error_data: T) {
 }
 
 pub fn shutdown(status: ErrorStatus) -> ! {
+    keyboard::on_shutdown();
+
     std::process::exit(status as i32);
 }
@@ -1,5 +1,7 @@
 use std::collections::hash_map::DefaultHasher;
 use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::SystemTime;
 
 pub fn hash_of<T: Hash>(t: T) -> u64 {
     let mut state = DefaultHasher::new();
@@ -8,12 +10,28 @@ pub fn hash_of<T: Hash>(t: T) -> u64 {
     state.finish()
 }
 
+// binding ids, only need to be unique within one settings file
+pub fn new_id() -> String {
+    static NEXT: AtomicU64 = AtomicU64::new(0);
+
+    let seed = (
+        SystemTime::now(),
+        std::process::id(),
+        NEXT.fetch_add(1, Ordering::Relaxed),
+    );
+
+    format!("{:012x}", self::hash_of(seed) >> 16)
+}
+
 use std::{fs::File, io::Write};
 
 use anyhow::Context;
-use egui_keybinds::KeyCode;
 
-use crate::settings::Settings;
+use crate::{
+    input::{MouseButton, WheelDirection},
+    key::Key,
+    settings::Settings,
+};
 
 pub fn is_first_run(path: &str) -> bool {
     !std::path::Path::new(path).exists()
@@ -32,113 +50,326 @@ pub fn create_default_file(path: &str, toml_settings: Settings) -> anyhow::Resul
     Ok(())
 }
 
-pub fn rdev_to_egui(key: rdev::Key) -> anyhow::Result<KeyCode> {
+pub fn rdev_to_key(key: rdev::Key) -> Key {
     match key {
-        rdev::Key::Alt => Ok(KeyCode::LAlt),
-        rdev::Key::AltGr => Ok(KeyCode::LAlt),
-        rdev::Key::Backspace => Ok(KeyCode::Backspace),
-        rdev::Key::CapsLock => Ok(KeyCode::CapsLock),
-        rdev::Key::ControlLeft => Ok(KeyCode::LControl),
-        rdev::Key::ControlRight => Ok(KeyCode::RControl),
-        rdev::Key::Delete => Ok(KeyCode::Delete),
-        rdev::Key::DownArrow => Ok(KeyCode::DownArrow),
-        rdev::Key::End => Ok(KeyCode::End),
-        rdev::Key::Escape => Ok(KeyCode::Escape),
-        rdev::Key::F1 => Ok(KeyCode::F1),
-        rdev::Key::F10 => Ok(KeyCode::F10),
-        rdev::Key::F11 => Ok(KeyCode::F11),
-        rdev::Key::F12 => Ok(KeyCode::F12),
-        rdev::Key::F2 => Ok(KeyCode::F2),
-        rdev::Key::F3 => Ok(KeyCode::F3),
-        rdev::Key::F4 => Ok(KeyCode::F4),
-        rdev::Key::F5 => Ok(KeyCode::F5),
-        rdev::Key::F6 => Ok(KeyCode::F6),
-        rdev::Key::F7 => Ok(KeyCode::F7),
-        rdev::Key::F8 => Ok(KeyCode::F8),
-        rdev::Key::F9 => Ok(KeyCode::F9),
-        rdev::Key::Home => Ok(KeyCode::Home),
-        rdev::Key::LeftArrow => Ok(KeyCode::LeftArrow),
-        rdev::Key::MetaLeft => Ok(KeyCode::LWindows),
-        rdev::Key::MetaRight => Ok(KeyCode::RWindows),
-        rdev::Key::PageDown => Ok(KeyCode::PageDown),
-        rdev::Key::PageUp => Ok(KeyCode::PageUp),
-        rdev::Key::Return => Ok(KeyCode::Return),
-        rdev::Key::RightArrow => Ok(KeyCode::RightArrow),
-        rdev::Key::ShiftLeft => Ok(KeyCode::LShift),
-        rdev::Key::ShiftRight => Ok(KeyCode::RShift),
-        rdev::Key::Space => Ok(KeyCode::Space),
-        rdev::Key::Tab => Ok(KeyCode::Tab),
-        rdev::Key::UpArrow => Ok(KeyCode::UpArrow),
-        rdev::Key::PrintScreen => anyhow::bail!("PrintScreen not supported yet!"),
-        rdev::Key::ScrollLock => anyhow::bail!("ScrollLock not supported yet!"),
-        rdev::Key::Pause => anyhow::bail!("Pause not supported yet!"),
-        rdev::Key::NumLock => anyhow::bail!("NumLock not supported yet!"),
-        rdev::Key::BackQuote => Ok(KeyCode::Backtick),
-        rdev::Key::Num1 => Ok(KeyCode::Num1),
-        rdev::Key::Num2 => Ok(KeyCode::Num2),
-        rdev::Key::Num3 => Ok(KeyCode::Num3),
-        rdev::Key::Num4 => Ok(KeyCode::Num4),
-        rdev::Key::Num5 => Ok(KeyCode::Num5),
-        rdev::Key::Num6 => Ok(KeyCode::Num6),
-        rdev::Key::Num7 => Ok(KeyCode::Num7),
-        rdev::Key::Num8 => Ok(KeyCode::Num8),
-        rdev::Key::Num9 => Ok(KeyCode::Num9),
-        rdev::Key::Num0 => Ok(KeyCode::Num0),
-        rdev::Key::Minus => Ok(KeyCode::Minus),
-        rdev::Key::Equal => Ok(KeyCode::Equals),
-        rdev::Key::KeyQ => Ok(KeyCode::Q),
-        rdev::Key::KeyW => Ok(KeyCode::W),
-        rdev::Key::KeyE => Ok(KeyCode::E),
-        rdev::Key::KeyR => Ok(KeyCode::R),
-        rdev::Key::KeyT => Ok(KeyCode::T),
-        rdev::Key::KeyY => Ok(KeyCode::Y),
-        rdev::Key::KeyU => Ok(KeyCode::U),
-        rdev::Key::KeyI => Ok(KeyCode::I),
-        rdev::Key::KeyO => Ok(KeyCode::O),
-        rdev::Key::KeyP => Ok(KeyCode::P),
-        rdev::Key::LeftBracket => Ok(KeyCode::OpenBracket),
-        rdev::Key::RightBracket => Ok(KeyCode::CloseBracket),
-        rdev::Key::KeyA => Ok(KeyCode::A),
-        rdev::Key::KeyS => Ok(KeyCode::S),
-        rdev::Key::KeyD => Ok(KeyCode::D),
-        rdev::Key::KeyF => Ok(KeyCode::F),
-        rdev::Key::KeyG => Ok(KeyCode::G),
-        rdev::Key::KeyH => Ok(KeyCode::H),
-        rdev::Key::KeyJ => Ok(KeyCode::J),
-        rdev::Key::KeyK => Ok(KeyCode::K),
-        rdev::Key::KeyL => Ok(KeyCode::L),
-        rdev::Key::SemiColon => Ok(KeyCode::SemiColon),
-        rdev::Key::Quote => Ok(KeyCode::Apostrophe),
-        rdev::Key::BackSlash => Ok(KeyCode::Backslash),
-        rdev::Key::IntlBackslash => Ok(KeyCode::Backslash),
-        rdev::Key::KeyZ => Ok(KeyCode::Z),
-        rdev::Key::KeyX => Ok(KeyCode::X),
-        rdev::Key::KeyC => Ok(KeyCode::C),
-        rdev::Key::KeyV => Ok(KeyCode::V),
-        rdev::Key::KeyB => Ok(KeyCode::B),
-        rdev::Key::KeyN => Ok(KeyCode::N),
-        rdev::Key::KeyM => Ok(KeyCode::M),
-        rdev::Key::Comma => Ok(KeyCode::Comma),
-        rdev::Key::Dot => Ok(KeyCode::Period),
-        rdev::Key::Slash => Ok(KeyCode::ForwardSlash),
-        rdev::Key::Insert => Ok(KeyCode::Insert),
-        rdev::Key::KpReturn => Ok(KeyCode::Return),
-        rdev::Key::KpMinus => Ok(KeyCode::Minus),
-        rdev::Key::KpPlus => Ok(KeyCode::Plus),
-        rdev::Key::KpMultiply => Ok(KeyCode::Asterisks),
-        rdev::Key::KpDivide => Ok(KeyCode::ForwardSlash),
-        rdev::Key::Kp0 => Ok(KeyCode::Num0),
-        rdev::Key::Kp1 => Ok(KeyCode::Num1),
-        rdev::Key::Kp2 => Ok(KeyCode::Num2),
-        rdev::Key::Kp3 => Ok(KeyCode::Num3),
-        rdev::Key::Kp4 => Ok(KeyCode::Num4),
-        rdev::Key::Kp5 => Ok(KeyCode::Num5),
-        rdev::Key::Kp6 => Ok(KeyCode::Num6),
-        rdev::Key::Kp7 => Ok(KeyCode::Num7),
-        rdev::Key::Kp8 => Ok(KeyCode::Num8),
-        rdev::Key::Kp9 => Ok(KeyCode::Num9),
-        rdev::Key::KpDelete => Ok(KeyCode::Delete),
-        rdev::Key::Function => Ok(KeyCode::LFunction),
-        rdev::Key::Unknown(code) => anyhow::bail!("Unknown rdev key: {}", code),
+        rdev::Key::Alt => Key::LAlt,
+        rdev::Key::AltGr => Key::RAlt,
+        rdev::Key::Backspace => Key::Backspace,
+        rdev::Key::CapsLock => Key::CapsLock,
+        rdev::Key::ControlLeft => Key::LControl,
+        rdev::Key::ControlRight => Key::RControl,
+        rdev::Key::Delete => Key::Delete,
+        rdev::Key::DownArrow => Key::DownArrow,
+        rdev::Key::End => Key::End,
+        rdev::Key::Escape => Key::Escape,
+        rdev::Key::F1 => Key::F1,
+        rdev::Key::F2 => Key::F2,
+        rdev::Key::F3 => Key::F3,
+        rdev::Key::F4 => Key::F4,
+        rdev::Key::F5 => Key::F5,
+        rdev::Key::F6 => Key::F6,
+        rdev::Key::F7 => Key::F7,
+        rdev::Key::F8 => Key::F8,
+        rdev::Key::F9 => Key::F9,
+        rdev::Key::F10 => Key::F10,
+        rdev::Key::F11 => Key::F11,
+        rdev::Key::F12 => Key::F12,
+        rdev::Key::Home => Key::Home,
+        rdev::Key::LeftArrow => Key::LeftArrow,
+        rdev::Key::MetaLeft => Key::LWindows,
+        rdev::Key::MetaRight => Key::RWindows,
+        rdev::Key::PageDown => Key::PageDown,
+        rdev::Key::PageUp => Key::PageUp,
+        rdev::Key::Return => Key::Return,
+        rdev::Key::RightArrow => Key::RightArrow,
+        rdev::Key::ShiftLeft => Key::LShift,
+        rdev::Key::ShiftRight => Key::RShift,
+        rdev::Key::Space => Key::Space,
+        rdev::Key::Tab => Key::Tab,
+        rdev::Key::UpArrow => Key::UpArrow,
+        rdev::Key::PrintScreen => Key::PrintScreen,
+        rdev::Key::ScrollLock => Key::ScrollLock,
+        rdev::Key::Pause => Key::Pause,
+        rdev::Key::NumLock => Key::NumLock,
+        rdev::Key::BackQuote => Key::Backtick,
+        rdev::Key::Num1 => Key::Num1,
+        rdev::Key::Num2 => Key::Num2,
+        rdev::Key::Num3 => Key::Num3,
+        rdev::Key::Num4 => Key::Num4,
+        rdev::Key::Num5 => Key::Num5,
+        rdev::Key::Num6 => Key::Num6,
+        rdev::Key::Num7 => Key::Num7,
+        rdev::Key::Num8 => Key::Num8,
+        rdev::Key::Num9 => Key::Num9,
+        rdev::Key::Num0 => Key::Num0,
+        rdev::Key::Minus => Key::Minus,
+        rdev::Key::Equal => Key::Equals,
+        rdev::Key::KeyQ => Key::Q,
+        rdev::Key::KeyW => Key::W,
+        rdev::Key::KeyE => Key::E,
+        rdev::Key::KeyR => Key::R,
+        rdev::Key::KeyT => Key::T,
+        rdev::Key::KeyY => Key::Y,
+        rdev::Key::KeyU => Key::U,
+        rdev::Key::KeyI => Key::I,
+        rdev::Key::KeyO => Key::O,
+        rdev::Key::KeyP => Key::P,
+        rdev::Key::LeftBracket => Key::OpenBracket,
+        rdev::Key::RightBracket => Key::CloseBracket,
+        rdev::Key::KeyA => Key::A,
+        rdev::Key::KeyS => Key::S,
+        rdev::Key::KeyD => Key::D,
+        rdev::Key::KeyF => Key::F,
+        rdev::Key::KeyG => Key::G,
+        rdev::Key::KeyH => Key::H,
+        rdev::Key::KeyJ => Key::J,
+        rdev::Key::KeyK => Key::K,
+        rdev::Key::KeyL => Key::L,
+        rdev::Key::SemiColon => Key::SemiColon,
+        rdev::Key::Quote => Key::Apostrophe,
+        rdev::Key::BackSlash => Key::Backslash,
+        rdev::Key::IntlBackslash => Key::IntlBackslash,
+        rdev::Key::KeyZ => Key::Z,
+        rdev::Key::KeyX => Key::X,
+        rdev::Key::KeyC => Key::C,
+        rdev::Key::KeyV => Key::V,
+        rdev::Key::KeyB => Key::B,
+        rdev::Key::KeyN => Key::N,
+        rdev::Key::KeyM => Key::M,
+        rdev::Key::Comma => Key::Comma,
+        rdev::Key::Dot => Key::Period,
+        rdev::Key::Slash => Key::ForwardSlash,
+        rdev::Key::Insert => Key::Insert,
+        rdev::Key::KpReturn => Key::KpReturn,
+        rdev::Key::KpMinus => Key::KpMinus,
+        rdev::Key::KpPlus => Key::KpPlus,
+        rdev::Key::KpMultiply => Key::KpMultiply,
+        rdev::Key::KpDivide => Key::KpDivide,
+        rdev::Key::Kp0 => Key::Kp0,
+        rdev::Key::Kp1 => Key::Kp1,
+        rdev::Key::Kp2 => Key::Kp2,
+        rdev::Key::Kp3 => Key::Kp3,
+        rdev::Key::Kp4 => Key::Kp4,
+        rdev::Key::Kp5 => Key::Kp5,
+        rdev::Key::Kp6 => Key::Kp6,
+        rdev::Key::Kp7 => Key::Kp7,
+        rdev::Key::Kp8 => Key::Kp8,
+        rdev::Key::Kp9 => Key::Kp9,
+        rdev::Key::KpDelete => Key::KpDelete,
+        rdev::Key::Function => Key::LFunction,
+        rdev::Key::Unknown(code) => self::unknown_to_key(code),
+    }
+}
+
+// keys rdev has no name for, by windows virtual key code
+#[cfg(target_os = "windows")]
+fn unknown_to_key(code: u32) -> Key {
+    match code {
+        0x5C => Key::RWindows,
+        0x5D => Key::Menu,
+        0x7C..=0x87 => FUNCTION_KEYS[(code - 0x7C) as usize],
+        0xAD => Key::VolumeMute,
+        0xAE => Key::VolumeDown,
+        0xAF => Key::VolumeUp,
+        0xB0 => Key::MediaNext,
+        0xB1 => Key::MediaPrevious,
+        0xB2 => Key::MediaStop,
+        0xB3 => Key::MediaPlayPause,
+        _ => Key::Unknown(code),
+    }
+}
+
+// keys rdev has no name for, by x11 keycode (evdev code + 8)
+#[cfg(target_os = "linux")]
+fn unknown_to_key(code: u32) -> Key {
+    match code {
+        121 => Key::VolumeMute,
+        122 => Key::VolumeDown,
+        123 => Key::VolumeUp,
+        134 => Key::RWindows,
+        135 => Key::Menu,
+        171 => Key::MediaNext,
+        172 => Key::MediaPlayPause,
+        173 => Key::MediaPrevious,
+        174 => Key::MediaStop,
+        191..=202 => FUNCTION_KEYS[(code - 191) as usize],
+        _ => Key::Unknown(code),
     }
 }
+
+#[cfg(not(any(target_os = "linux", target_os = "windows")))]
+fn unknown_to_key(code: u32) -> Key {
+    Key::Unknown(code)
+}
+
+const FUNCTION_KEYS: [Key; 12] = [
+    Key::F13,
+    Key::F14,
+    Key::F15,
+    Key::F16,
+    Key::F17,
+    Key::F18,
+    Key::F19,
+    Key::F20,
+    Key::F21,
+    Key::F22,
+    Key::F23,
+    Key::F24,
+];
+
+pub fn rdev_to_mouse(button: rdev::Button) -> anyhow::Result<MouseButton> {
+    match button {
+        rdev::Button::Left => Ok(MouseButton::Left),
+        rdev::Button::Right => Ok(MouseButton::Right),
+        rdev::Button::Middle => Ok(MouseButton::Middle),
+        // side buttons are XBUTTON1/2 on windows and buttons 8/9 on x11
+        rdev::Button::Unknown(1) | rdev::Button::Unknown(8) => Ok(MouseButton::Back),
+        rdev::Button::Unknown(2) | rdev::Button::Unknown(9) => Ok(MouseButton::Forward),
+        rdev::Button::Unknown(code) => anyhow::bail!("Unknown rdev button: {}", code),
+    }
+}
+
+// one direction per notch scrolled, rdev reports up and right as positive
+pub fn rdev_to_wheel(delta_x: i64, delta_y: i64) -> Vec<WheelDirection> {
+    let vertical = if delta_y > 0 {
+        WheelDirection::Up
+    } else {
+        WheelDirection::Down
+    };
+
+    let horizontal = if delta_x > 0 {
+        WheelDirection::Right
+    } else {
+        WheelDirection::Left
+    };
+
+    let mut directions = vec![vertical; delta_y.unsigned_abs() as usize];
+    directions.extend(vec![horizontal; delta_x.unsigned_abs() as usize]);
+
+    directions
+}
+
+// linux evdev codes for each key, as positioned on a us qwerty layout
+#[rustfmt::skip]
+const SCANCODES: [(Key, u32); 125] = [
+    (Key::Escape, 1), (Key::Num1, 2), (Key::Num2, 3), (Key::Num3, 4), (Key::Num4, 5),
+    (Key::Num5, 6), (Key::Num6, 7), (Key::Num7, 8), (Key::Num8, 9), (Key::Num9, 10),
+    (Key::Num0, 11), (Key::Minus, 12), (Key::Equals, 13), (Key::Backspace, 14), (Key::Tab, 15),
+    (Key::Q, 16), (Key::W, 17), (Key::E, 18), (Key::R, 19), (Key::T, 20), (Key::Y, 21),
+    (Key::U, 22), (Key::I, 23), (Key::O, 24), (Key::P, 25), (Key::OpenBracket, 26),
+    (Key::CloseBracket, 27), (Key::Return, 28), (Key::LControl, 29), (Key::A, 30), (Key::S, 31),
+    (Key::D, 32), (Key::F, 33), (Key::G, 34), (Key::H, 35), (Key::J, 36), (Key::K, 37),
+    (Key::L, 38), (Key::SemiColon, 39), (Key::Apostrophe, 40), (Key::Backtick, 41),
+    (Key::LShift, 42), (Key::Backslash, 43), (Key::Z, 44), (Key::X, 45), (Key::C, 46), (Key::V, 47),
+    (Key::B, 48), (Key::N, 49), (Key::M, 50), (Key::Comma, 51), (Key::Period, 52),
+    (Key::ForwardSlash, 53), (Key::RShift, 54), (Key::KpMultiply, 55), (Key::LAlt, 56),
+    (Key::Space, 57), (Key::CapsLock, 58), (Key::F1, 59), (Key::F2, 60), (Key::F3, 61),
+    (Key::F4, 62), (Key::F5, 63), (Key::F6, 64), (Key::F7, 65), (Key::F8, 66), (Key::F9, 67),
+    (Key::F10, 68), (Key::NumLock, 69), (Key::ScrollLock, 70), (Key::Kp7, 71), (Key::Kp8, 72),
+    (Key::Kp9, 73), (Key::KpMinus, 74), (Key::Kp4, 75), (Key::Kp5, 76), (Key::Kp6, 77),
+    (Key::KpPlus, 78), (Key::Kp1, 79), (Key::Kp2, 80), (Key::Kp3, 81), (Key::Kp0, 82),
+    (Key::KpDelete, 83), (Key::IntlBackslash, 86), (Key::F11, 87), (Key::F12, 88),
+    (Key::KpReturn, 96), (Key::RControl, 97), (Key::KpDivide, 98), (Key::PrintScreen, 99),
+    (Key::RAlt, 100), (Key::Home, 102), (Key::UpArrow, 103), (Key::PageUp, 104),
+    (Key::LeftArrow, 105), (Key::RightArrow, 106), (Key::End, 107), (Key::DownArrow, 108),
+    (Key::PageDown, 109), (Key::Insert, 110), (Key::Delete, 111), (Key::VolumeMute, 113),
+    (Key::VolumeDown, 114), (Key::VolumeUp, 115), (Key::Pause, 119), (Key::LWindows, 125),
+    (Key::RWindows, 126), (Key::Menu, 127), (Key::MediaNext, 163), (Key::MediaPlayPause, 164),
+    (Key::MediaPrevious, 165), (Key::MediaStop, 166), (Key::F13, 183), (Key::F14, 184),
+    (Key::F15, 185), (Key::F16, 186), (Key::F17, 187), (Key::F18, 188), (Key::F19, 189),
+    (Key::F20, 190), (Key::F21, 191), (Key::F22, 192), (Key::F23, 193), (Key::F24, 194),
+    (Key::LFunction, 464),
+];
+
+pub fn key_to_scancode(key: &Key) -> Option<u32> {
+    SCANCODES
+        .iter()
+        .find(|(k, _)| k == key)
+        .map(|(_, code)| *code)
+}
+
+pub fn scancode_to_key(scancode: u32) -> Option<Key> {
+    SCANCODES
+        .iter()
+        .find(|(_, code)| *code == scancode)
+        .map(|(key, _)| *key)
+}
+
+// x11 keycodes are physical, rdev names keys by position and passes unknown codes through
+#[cfg(target_os = "linux")]
+pub fn rdev_to_scancode(key: rdev::Key) -> Option<u32> {
+    match key {
+        rdev::Key::Unknown(code) => code.checked_sub(8),
+        _ => self::key_to_scancode(&self::rdev_to_key(key)),
+    }
+}
+
+// windows names keys by virtual key, which follows the layout, ask windows where it really is
+#[cfg(target_os = "windows")]
+pub fn rdev_to_scancode(key: rdev::Key) -> Option<u32> {
+    use winapi::um::winuser::{MapVirtualKeyW, MAPVK_VK_TO_VSC};
+
+    let virtual_key = match key {
+        rdev::Key::KeyA => 0x41,
+        rdev::Key::KeyB => 0x42,
+        rdev::Key::KeyC => 0x43,
+        rdev::Key::KeyD => 0x44,
+        rdev::Key::KeyE => 0x45,
+        rdev::Key::KeyF => 0x46,
+        rdev::Key::KeyG => 0x47,
+        rdev::Key::KeyH => 0x48,
+        rdev::Key::KeyI => 0x49,
+        rdev::Key::KeyJ => 0x4A,
+        rdev::Key::KeyK => 0x4B,
+        rdev::Key::KeyL => 0x4C,
+        rdev::Key::KeyM => 0x4D,
+        rdev::Key::KeyN => 0x4E,
+        rdev::Key::KeyO => 0x4F,
+        rdev::Key::KeyP => 0x50,
+        rdev::Key::KeyQ => 0x51,
+        rdev::Key::KeyR => 0x52,
+        rdev::Key::KeyS => 0x53,
+        rdev::Key::KeyT => 0x54,
+        rdev::Key::KeyU => 0x55,
+        rdev::Key::KeyV => 0x56,
+        rdev::Key::KeyW => 0x57,
+        rdev::Key::KeyX => 0x58,
+        rdev::Key::KeyY => 0x59,
+        rdev::Key::KeyZ => 0x5A,
+        rdev::Key::Num0 => 0x30,
+        rdev::Key::Num1 => 0x31,
+        rdev::Key::Num2 => 0x32,
+        rdev::Key::Num3 => 0x33,
+        rdev::Key::Num4 => 0x34,
+        rdev::Key::Num5 => 0x35,
+        rdev::Key::Num6 => 0x36,
+        rdev::Key::Num7 => 0x37,
+        rdev::Key::Num8 => 0x38,
+        rdev::Key::Num9 => 0x39,
+        rdev::Key::SemiColon => 186,
+        rdev::Key::Equal => 187,
+        rdev::Key::Comma => 188,
+        rdev::Key::Minus => 189,
+        rdev::Key::Dot => 190,
+        rdev::Key::Slash => 191,
+        rdev::Key::BackQuote => 192,
+        rdev::Key::LeftBracket => 219,
+        rdev::Key::BackSlash => 220,
+        rdev::Key::RightBracket => 221,
+        rdev::Key::Quote => 222,
+        rdev::Key::IntlBackslash => 226,
+        // windows drops the e0 prefix for these, media keys and f13+ would land on other keys
+        rdev::Key::Unknown(code) => return self::key_to_scancode(&self::unknown_to_key(code)),
+        _ => return self::key_to_scancode(&self::rdev_to_key(key)), // named keys don't move between layouts
+    };
+
+    match unsafe { MapVirtualKeyW(virtual_key, MAPVK_VK_TO_VSC) } {
+        0 => self::key_to_scancode(&self::rdev_to_key(key)),
+        code => Some(code),
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "windows")))]
+pub fn rdev_to_scancode(key: rdev::Key) -> Option<u32> {
+    self::key_to_scancode(&self::rdev_to_key(key))
+}
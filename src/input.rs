@@ -0,0 +1,264 @@
+extern crate lazy_static;
+
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use lazy_static::lazy_static;
+use serde::Serialize;
+
+use crate::key::{Key, KeyBind, Modifier};
+
+lazy_static! {
+    // monotonic timestamps count from here, so they fit comfortably in a js number
+    static ref EPOCH: Instant = Instant::now();
+}
+
+// wall clock time of an event plus a monotonic counter that can't jump
+#[derive(Clone, Copy)]
+pub struct Timestamp {
+    pub system: SystemTime,
+    pub monotonic_ns: u64,
+}
+
+impl Timestamp {
+    // placed on the monotonic clock by how long ago it happened, so hold times measure the
+    // events themselves rather than how long the hook took to hand each one over
+    pub fn new(system: SystemTime) -> Self {
+        let age = SystemTime::now().duration_since(system).unwrap_or_default();
+
+        Self {
+            system,
+            monotonic_ns: EPOCH.elapsed().saturating_sub(age).as_nanos() as u64,
+        }
+    }
+
+    pub fn unix_ms(&self) -> f64 {
+        match self.system.duration_since(UNIX_EPOCH) {
+            Ok(duration) => duration.as_secs_f64() * 1000_f64,
+            Err(_) => 0_f64,
+        }
+    }
+}
+
+// the physical device an event came from, for backends that can tell them apart
+#[derive(Clone, PartialEq, Eq, Hash, Serialize)]
+pub struct Device {
+    pub id: String, // stays the same across replugs and reboots
+    pub name: String,
+}
+
+impl Device {
+    pub fn new(id: String, name: String) -> Self {
+        Self { id, name }
+    }
+
+    // filters are either the exact id or part of the name
+    pub fn matches(&self, filter: &str) -> bool {
+        self.id == filter || self.name.to_lowercase().contains(&filter.to_lowercase())
+    }
+}
+
+// a press or release as every backend reports it
+#[derive(Clone)]
+pub struct InputEvent {
+    pub input: Input,
+    pub scancode: Option<u32>,
+    pub is_down: bool,
+    pub time: Timestamp,
+    pub device: Option<Device>,
+}
+
+impl InputEvent {
+    pub fn new(input: Input, scancode: Option<u32>, is_down: bool, time: Timestamp) -> Self {
+        Self {
+            input,
+            scancode,
+            is_down,
+            time,
+            device: None,
+        }
+    }
+
+    pub fn with_device(self, device: Option<Device>) -> Self {
+        Self { device, ..self }
+    }
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Hash, Debug)]
+pub enum MouseButton {
+    Left,
+    Right,
+    Middle,
+    Back,
+    Forward,
+}
+
+impl MouseButton {
+    pub const ALL: [MouseButton; 5] = [
+        MouseButton::Left,
+        MouseButton::Right,
+        MouseButton::Middle,
+        MouseButton::Back,
+        MouseButton::Forward,
+    ];
+
+    pub fn serialize(&self) -> String {
+        match self {
+            MouseButton::Left => "MouseLeft",
+            MouseButton::Right => "MouseRight",
+            MouseButton::Middle => "MouseMiddle",
+            MouseButton::Back => "MouseBack",
+            MouseButton::Forward => "MouseForward",
+        }
+        .to_string()
+    }
+
+    pub fn deserialize(data: &str) -> Result<Self, ()> {
+        Self::ALL
+            .into_iter()
+            .find(|button| button.serialize().eq_ignore_ascii_case(data))
+            .ok_or(())
+    }
+}
+
+// binds without modifiers fire whatever else is held, like wasd while shift sprints
+// active modifiers are expected to be sorted and deduplicated
+pub fn modifiers_match(bind_modifiers: &[Modifier], active: &[Modifier]) -> bool {
+    if bind_modifiers.is_empty() {
+        return true;
+    }
+
+    let mut bind_modifiers = bind_modifiers.to_vec();
+
+    bind_modifiers.sort();
+    bind_modifiers.dedup();
+
+    bind_modifiers == active
+}
+
+// wheel directions are virtual keys, they are pulsed instead of held
+#[derive(PartialEq, Eq, Clone, Copy, Hash, Debug)]
+pub enum WheelDirection {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+impl WheelDirection {
+    pub const ALL: [WheelDirection; 4] = [
+        WheelDirection::Up,
+        WheelDirection::Down,
+        WheelDirection::Left,
+        WheelDirection::Right,
+    ];
+
+    pub fn serialize(&self) -> String {
+        match self {
+            WheelDirection::Up => "WheelUp",
+            WheelDirection::Down => "WheelDown",
+            WheelDirection::Left => "WheelLeft",
+            WheelDirection::Right => "WheelRight",
+        }
+        .to_string()
+    }
+
+    pub fn deserialize(data: &str) -> Result<Self, ()> {
+        Self::ALL
+            .into_iter()
+            .find(|direction| direction.serialize().eq_ignore_ascii_case(data))
+            .ok_or(())
+    }
+}
+
+// anything that can be held down and reported to clients
+#[derive(PartialEq, Eq, Clone, Hash, Debug)]
+pub enum Input {
+    Key(Key),
+    Mouse(MouseButton),
+    Wheel(WheelDirection),
+}
+
+impl Input {
+    pub fn serialize(&self) -> String {
+        match self {
+            Input::Key(key) => key.serialize(),
+            Input::Mouse(button) => button.serialize(),
+            Input::Wheel(direction) => direction.serialize(),
+        }
+    }
+
+    // every input that isn't a keyboard key, in the order the gui lists them
+    pub fn pointer_inputs() -> Vec<Input> {
+        let buttons = MouseButton::ALL.into_iter().map(Input::Mouse);
+        let directions = WheelDirection::ALL.into_iter().map(Input::Wheel);
+
+        buttons.chain(directions).collect()
+    }
+}
+
+// a configured binding, keyboard binds keep their modifiers
+#[derive(Clone, Hash)]
+pub enum Bind {
+    Key(KeyBind),
+    Mouse(MouseButton),
+    Wheel(WheelDirection),
+}
+
+impl Bind {
+    pub fn empty() -> Self {
+        Bind::Key(KeyBind::empty())
+    }
+
+    pub fn input(&self) -> Option<Input> {
+        match self {
+            Bind::Key(bind) => bind.key.map(Input::Key),
+            Bind::Mouse(button) => Some(Input::Mouse(*button)),
+            Bind::Wheel(direction) => Some(Input::Wheel(*direction)),
+        }
+    }
+
+    // keyboard binds with modifiers need exactly those held, pointer binds ignore them
+    pub fn matches_modifiers(&self, active: &[Modifier]) -> bool {
+        match self {
+            Bind::Key(bind) => self::modifiers_match(&bind.modifiers, active),
+            _ => true,
+        }
+    }
+
+    pub fn pointer_input(&self) -> Option<Input> {
+        match self {
+            Bind::Key(_) => None,
+            _ => self.input(),
+        }
+    }
+
+    pub fn serialize(&self) -> String {
+        match self {
+            Bind::Key(bind) => bind.serialize(),
+            Bind::Mouse(button) => button.serialize(),
+            Bind::Wheel(direction) => direction.serialize(),
+        }
+    }
+
+    pub fn deserialize(data: &str) -> Result<Self, ()> {
+        if let Ok(button) = MouseButton::deserialize(data) {
+            return Ok(Bind::Mouse(button));
+        }
+
+        if let Ok(direction) = WheelDirection::deserialize(data) {
+            return Ok(Bind::Wheel(direction));
+        }
+
+        KeyBind::deserialize(data).map(Bind::Key)
+    }
+}
+
+impl From<Input> for Bind {
+    fn from(input: Input) -> Self {
+        match input {
+            Input::Key(key) => Bind::Key(KeyBind::new(Some(key), vec![])),
+            Input::Mouse(button) => Bind::Mouse(button),
+            Input::Wheel(direction) => Bind::Wheel(direction),
+        }
+    }
+}
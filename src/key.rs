@@ -0,0 +1,467 @@
+// every key the input backends can report, unknown platform codes are kept as is
+#[derive(PartialEq, Eq, Clone, Copy, Hash, Debug)]
+pub enum Key {
+    Escape,
+    F1,
+    F2,
+    F3,
+    F4,
+    F5,
+    F6,
+    F7,
+    F8,
+    F9,
+    F10,
+    F11,
+    F12,
+    F13,
+    F14,
+    F15,
+    F16,
+    F17,
+    F18,
+    F19,
+    F20,
+    F21,
+    F22,
+    F23,
+    F24,
+    Backtick,
+    Num0,
+    Num1,
+    Num2,
+    Num3,
+    Num4,
+    Num5,
+    Num6,
+    Num7,
+    Num8,
+    Num9,
+    Minus,
+    Equals,
+    Backspace,
+    Tab,
+    Q,
+    W,
+    E,
+    R,
+    T,
+    Y,
+    U,
+    I,
+    O,
+    P,
+    OpenBracket,
+    CloseBracket,
+    Backslash,
+    CapsLock,
+    A,
+    S,
+    D,
+    F,
+    G,
+    H,
+    J,
+    K,
+    L,
+    SemiColon,
+    Apostrophe,
+    Return,
+    LShift,
+    IntlBackslash,
+    Z,
+    X,
+    C,
+    V,
+    B,
+    N,
+    M,
+    Comma,
+    Period,
+    ForwardSlash,
+    RShift,
+    LFunction,
+    LControl,
+    LWindows,
+    LAlt,
+    Space,
+    RAlt,
+    RWindows,
+    Menu,
+    RControl,
+    PrintScreen,
+    ScrollLock,
+    Pause,
+    Insert,
+    Delete,
+    Home,
+    End,
+    PageUp,
+    PageDown,
+    UpArrow,
+    DownArrow,
+    LeftArrow,
+    RightArrow,
+    NumLock,
+    KpDivide,
+    KpMultiply,
+    KpMinus,
+    KpPlus,
+    KpReturn,
+    Kp0,
+    Kp1,
+    Kp2,
+    Kp3,
+    Kp4,
+    Kp5,
+    Kp6,
+    Kp7,
+    Kp8,
+    Kp9,
+    KpDelete,
+    VolumeMute,
+    VolumeDown,
+    VolumeUp,
+    MediaPlayPause,
+    MediaStop,
+    MediaNext,
+    MediaPrevious,
+    Unknown(u32),
+}
+
+// names are shared with the old egui_keybinds names so existing settings keep working
+const KEY_NAMES: [(Key, &str); 125] = [
+    (Key::Escape, "Escape"),
+    (Key::F1, "F1"),
+    (Key::F2, "F2"),
+    (Key::F3, "F3"),
+    (Key::F4, "F4"),
+    (Key::F5, "F5"),
+    (Key::F6, "F6"),
+    (Key::F7, "F7"),
+    (Key::F8, "F8"),
+    (Key::F9, "F9"),
+    (Key::F10, "F10"),
+    (Key::F11, "F11"),
+    (Key::F12, "F12"),
+    (Key::F13, "F13"),
+    (Key::F14, "F14"),
+    (Key::F15, "F15"),
+    (Key::F16, "F16"),
+    (Key::F17, "F17"),
+    (Key::F18, "F18"),
+    (Key::F19, "F19"),
+    (Key::F20, "F20"),
+    (Key::F21, "F21"),
+    (Key::F22, "F22"),
+    (Key::F23, "F23"),
+    (Key::F24, "F24"),
+    (Key::Backtick, "`"),
+    (Key::Num0, "0"),
+    (Key::Num1, "1"),
+    (Key::Num2, "2"),
+    (Key::Num3, "3"),
+    (Key::Num4, "4"),
+    (Key::Num5, "5"),
+    (Key::Num6, "6"),
+    (Key::Num7, "7"),
+    (Key::Num8, "8"),
+    (Key::Num9, "9"),
+    (Key::Minus, "-"),
+    (Key::Equals, "="),
+    (Key::Backspace, "Backspace"),
+    (Key::Tab, "Tab"),
+    (Key::Q, "Q"),
+    (Key::W, "W"),
+    (Key::E, "E"),
+    (Key::R, "R"),
+    (Key::T, "T"),
+    (Key::Y, "Y"),
+    (Key::U, "U"),
+    (Key::I, "I"),
+    (Key::O, "O"),
+    (Key::P, "P"),
+    (Key::OpenBracket, "["),
+    (Key::CloseBracket, "]"),
+    (Key::Backslash, "\\"),
+    (Key::CapsLock, "CapsLock"),
+    (Key::A, "A"),
+    (Key::S, "S"),
+    (Key::D, "D"),
+    (Key::F, "F"),
+    (Key::G, "G"),
+    (Key::H, "H"),
+    (Key::J, "J"),
+    (Key::K, "K"),
+    (Key::L, "L"),
+    (Key::SemiColon, ";"),
+    (Key::Apostrophe, "'"),
+    (Key::Return, "Return"),
+    (Key::LShift, "LShift"),
+    (Key::IntlBackslash, "IntlBackslash"),
+    (Key::Z, "Z"),
+    (Key::X, "X"),
+    (Key::C, "C"),
+    (Key::V, "V"),
+    (Key::B, "B"),
+    (Key::N, "N"),
+    (Key::M, "M"),
+    (Key::Comma, ","),
+    (Key::Period, "."),
+    (Key::ForwardSlash, "/"),
+    (Key::RShift, "RShift"),
+    (Key::LFunction, "LFunction"),
+    (Key::LControl, "LControl"),
+    (Key::LWindows, "LWindows"),
+    (Key::LAlt, "LAlt"),
+    (Key::Space, "Space"),
+    (Key::RAlt, "RAlt"),
+    (Key::RWindows, "RWindows"),
+    (Key::Menu, "Menu"),
+    (Key::RControl, "RControl"),
+    (Key::PrintScreen, "PrintScreen"),
+    (Key::ScrollLock, "ScrollLock"),
+    (Key::Pause, "Pause"),
+    (Key::Insert, "Insert"),
+    (Key::Delete, "Delete"),
+    (Key::Home, "Home"),
+    (Key::End, "End"),
+    (Key::PageUp, "PageUp"),
+    (Key::PageDown, "PageDown"),
+    (Key::UpArrow, "UpArrow"),
+    (Key::DownArrow, "DownArrow"),
+    (Key::LeftArrow, "LeftArrow"),
+    (Key::RightArrow, "RightArrow"),
+    (Key::NumLock, "NumLock"),
+    (Key::KpDivide, "KpDivide"),
+    (Key::KpMultiply, "KpMultiply"),
+    (Key::KpMinus, "KpMinus"),
+    (Key::KpPlus, "KpPlus"),
+    (Key::KpReturn, "KpReturn"),
+    (Key::Kp0, "Kp0"),
+    (Key::Kp1, "Kp1"),
+    (Key::Kp2, "Kp2"),
+    (Key::Kp3, "Kp3"),
+    (Key::Kp4, "Kp4"),
+    (Key::Kp5, "Kp5"),
+    (Key::Kp6, "Kp6"),
+    (Key::Kp7, "Kp7"),
+    (Key::Kp8, "Kp8"),
+    (Key::Kp9, "Kp9"),
+    (Key::KpDelete, "KpDelete"),
+    (Key::VolumeMute, "VolumeMute"),
+    (Key::VolumeDown, "VolumeDown"),
+    (Key::VolumeUp, "VolumeUp"),
+    (Key::MediaPlayPause, "MediaPlayPause"),
+    (Key::MediaStop, "MediaStop"),
+    (Key::MediaNext, "MediaNext"),
+    (Key::MediaPrevious, "MediaPrevious"),
+];
+
+// names older versions wrote for keys that were folded into others
+const KEY_ALIASES: [(Key, &str); 3] =
+    [(Key::Comma, "),"), (Key::KpPlus, "+"), (Key::RAlt, "AltGr")];
+
+impl Key {
+    pub fn serialize(&self) -> String {
+        if let Key::Unknown(code) = self {
+            return format!("Unknown({})", code);
+        }
+
+        KEY_NAMES
+            .iter()
+            .find(|(key, _)| key == self)
+            .map(|(_, name)| name.to_string())
+            .unwrap_or_default()
+    }
+
+    pub fn deserialize(data: &str) -> Result<Self, ()> {
+        if let Some(code) = data
+            .strip_prefix("Unknown(")
+            .and_then(|rest| rest.strip_suffix(')'))
+        {
+            return code.parse::<u32>().map(Key::Unknown).map_err(|_| ());
+        }
+
+        KEY_NAMES
+            .iter()
+            .chain(KEY_ALIASES.iter())
+            .find(|(_, name)| name.eq_ignore_ascii_case(data))
+            .map(|(key, _)| *key)
+            .ok_or(())
+    }
+
+    pub fn as_modifier(&self) -> Option<Modifier> {
+        match self {
+            Key::LControl | Key::RControl => Some(Modifier::Control),
+            Key::LWindows | Key::RWindows => Some(Modifier::Meta),
+            Key::LAlt | Key::RAlt => Some(Modifier::Alt),
+            Key::LShift | Key::RShift => Some(Modifier::Shift),
+            Key::LFunction => Some(Modifier::Function),
+            _ => None,
+        }
+    }
+}
+
+// discriminants keep sorted modifiers in the order Control, Alt, Shift, Function, Meta
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Hash, Debug)]
+pub enum Modifier {
+    Control = 1, // Control, or Command
+    Alt = 2,     // Alt, or Option
+    Shift = 3,
+    Function = 4,
+    Meta = 5, // Windows, or Super
+}
+
+impl Modifier {
+    pub fn serialize(&self) -> char {
+        match self {
+            Modifier::Control => '^',
+            Modifier::Alt => '*',
+            Modifier::Shift => '_',
+            Modifier::Function => '~',
+            Modifier::Meta => '#',
+        }
+    }
+
+    pub fn deserialize(data: char) -> Result<Self, ()> {
+        match data {
+            '^' => Ok(Modifier::Control),
+            '*' => Ok(Modifier::Alt),
+            '_' => Ok(Modifier::Shift),
+            '~' => Ok(Modifier::Function),
+            '#' => Ok(Modifier::Meta),
+            _ => Err(()),
+        }
+    }
+}
+
+// a key plus the modifiers that have to be held with it, serialized as e.g. "^_Z"
+#[derive(PartialEq, Eq, Clone, Hash, Debug)]
+pub struct KeyBind {
+    pub key: Option<Key>,
+    pub modifiers: Vec<Modifier>,
+}
+
+impl KeyBind {
+    pub fn new(key: Option<Key>, mut modifiers: Vec<Modifier>) -> Self {
+        modifiers.sort();
+        modifiers.dedup();
+
+        Self { key, modifiers }
+    }
+
+    pub fn empty() -> Self {
+        Self::new(None, vec![])
+    }
+
+    pub fn serialize(&self) -> String {
+        let key = match &self.key {
+            Some(key) => key,
+            None => return "...".to_string(),
+        };
+
+        let mut serialized = String::new();
+
+        for modifier in &self.modifiers {
+            serialized.push(modifier.serialize());
+        }
+
+        serialized.push_str(&key.serialize());
+        serialized
+    }
+
+    pub fn deserialize(data: &str) -> Result<Self, ()> {
+        if data == "..." {
+            return Ok(Self::empty());
+        }
+
+        let name = data.trim_start_matches(|ch| Modifier::deserialize(ch).is_ok());
+        let prefix = &data[..data.len() - name.len()];
+
+        let key = Key::deserialize(name)?;
+        let modifiers = prefix
+            .chars()
+            .filter_map(|ch| Modifier::deserialize(ch).ok())
+            .collect();
+
+        Ok(Self::new(Some(key), modifiers))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MODIFIERS: [Modifier; 5] = [
+        Modifier::Control,
+        Modifier::Alt,
+        Modifier::Shift,
+        Modifier::Function,
+        Modifier::Meta,
+    ];
+
+    fn round_trips(bind: &KeyBind) {
+        let serialized = bind.serialize();
+        assert_eq!(
+            KeyBind::deserialize(&serialized),
+            Ok(bind.clone()),
+            "{}",
+            serialized
+        );
+    }
+
+    #[test]
+    fn keys_read_back_the_same() {
+        for (key, name) in KEY_NAMES {
+            assert_eq!(key.serialize(), name);
+            assert_eq!(Key::deserialize(name), Ok(key));
+            assert_eq!(Key::deserialize(&name.to_lowercase()), Ok(key));
+        }
+
+        // aliases read as their key, which is then written under its own name
+        for (key, alias) in KEY_ALIASES {
+            assert_eq!(Key::deserialize(alias), Ok(key));
+            assert_eq!(Key::deserialize(&key.serialize()), Ok(key));
+        }
+
+        for code in [0, 1, 255, u32::MAX] {
+            let key = Key::Unknown(code);
+            assert_eq!(key.serialize(), format!("Unknown({})", code));
+            assert_eq!(Key::deserialize(&key.serialize()), Ok(key));
+        }
+
+        assert!(Key::deserialize("Unknown(-1)").is_err());
+        assert!(Key::deserialize("NotAKey").is_err());
+    }
+
+    #[test]
+    fn binds_read_back_the_same() {
+        let keys = KEY_NAMES
+            .iter()
+            .map(|(key, _)| *key)
+            .chain([Key::Unknown(0), Key::Unknown(u32::MAX)]);
+
+        for key in keys {
+            self::round_trips(&KeyBind::new(Some(key), vec![]));
+            self::round_trips(&KeyBind::new(Some(key), MODIFIERS.to_vec()));
+
+            for modifier in MODIFIERS {
+                self::round_trips(&KeyBind::new(Some(key), vec![modifier]));
+            }
+        }
+
+        self::round_trips(&KeyBind::empty());
+    }
+
+    #[test]
+    fn modifier_prefixes_are_read_in_any_order() {
+        for modifier in MODIFIERS {
+            assert_eq!(Modifier::deserialize(modifier.serialize()), Ok(modifier));
+        }
+
+        let bind = KeyBind::deserialize("#~_*^Z").unwrap();
+        assert_eq!(bind, KeyBind::new(Some(Key::Z), MODIFIERS.to_vec()));
+        assert_eq!(bind.serialize(), "^*_~#Z");
+    }
+}
@@ -4,10 +4,21 @@
 extern crate toml;
 extern crate const_format;
 
+mod backends;
+mod combo;
 mod delegates;
 mod error;
 mod helper;
+mod input;
+mod key;
+mod message;
+mod osu;
+mod protocol;
+mod rate;
+mod record;
 mod settings;
+mod state;
+mod window;
 
 use std::thread;
 
@@ -18,6 +29,7 @@ use settings::{OverlaySettings, Settings};
 use error::ErrorStatus;
 
 static SETTINGS_FILENAME: &str = "settings.toml";
+static COUNTERS_FILENAME: &str = "counters.toml";
 
 pub const NAME: &str = "keyoverlay-rs";
 pub const BUILD: &str = "stable";
@@ -57,7 +69,15 @@ fn load_configuration() -> anyhow::Result<OverlaySettings> {
             .with_context(|| "Failed to deserialize settings")?;
     }
 
-    OverlaySettings::from_toml(toml_settings)
+    let (settings, ids_changed) = OverlaySettings::from_toml(toml_settings)?;
+
+    // ids have to be written down straight away or they'd change every launch
+    if ids_changed {
+        std::fs::write(SETTINGS_FILENAME, settings.to_toml()?)
+            .with_context(|| "Failed to write ids to configuration file")?;
+    }
+
+    Ok(settings)
 }
 
 fn start_delegates(settings: OverlaySettings) {
@@ -71,6 +91,11 @@ fn start_delegates(settings: OverlaySettings) {
         start_delegate!(keyboard, keyboard_settings);
     });
 
+    let player_settings = settings.clone();
+    thread::spawn(move || {
+        start_delegate!(player, player_settings);
+    });
+
     start_delegate!(gui, settings);
 }
 
@@ -80,6 +105,9 @@ fn main() {
     match load_configuration() {
         Ok(settings) => {
             start_delegates(settings);
+
+            // the window was closed
+            error::shutdown(ErrorStatus::SUCCESS);
         }
 
         Err(error) => {
@@ -0,0 +1,85 @@
+use crate::{
+    input::{Device, Timestamp},
+    protocol::{Direction, Event, EventKind},
+};
+
+// a binding being pressed or released, as clients receive it
+pub struct KeyMessage<'a> {
+    pub key: Option<&'a str>, // the bind, when it's known
+    pub id: Option<&'a str>,  // stable id of the binding, when there is one
+    pub label: &'a str,
+    pub is_down: bool,
+    pub index: usize,
+    pub count: u64,
+    pub time: Timestamp,
+    pub hold_ns: Option<u64>,
+    pub device: Option<&'a Device>,
+}
+
+impl KeyMessage<'_> {
+    // ["label", is_down, index, count, unix ms, monotonic ns, hold ns | null, device | null]
+    pub fn to_json(&self) -> String {
+        serde_json::json!([
+            self.label,
+            self.is_down,
+            self.index,
+            self.count,
+            self.time.unix_ms(),
+            self.time.monotonic_ns,
+            self.hold_ns,
+            self.device,
+        ])
+        .to_string()
+    }
+
+    pub fn to_event(&self) -> Event {
+        let payload = serde_json::json!({
+            "count": self.count,
+            "hold_ns": self.hold_ns,
+            "device": self.device,
+        });
+
+        Event {
+            key: self.key.map(str::to_string),
+            id: self.id.map(str::to_string),
+            index: Some(self.index),
+            label: Some(self.label.to_string()),
+            direction: Some(Direction::of(self.is_down)),
+            ..Event::new(EventKind::Key, self.time)
+        }
+        .with_payload(payload)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::SystemTime;
+
+    use super::*;
+
+    #[test]
+    fn masks_with_quotes_and_backslashes_stay_valid_json() {
+        for mask in ["\"", "\\", "say \"hi\"", "C:\\keys\\", "\\\"", "\n\t"] {
+            let message = KeyMessage {
+                key: Some("_Z"),
+                id: Some("id\"\\"),
+                label: mask,
+                is_down: true,
+                index: 0,
+                count: 1,
+                time: Timestamp::new(SystemTime::now()),
+                hold_ns: None,
+                device: None,
+            };
+
+            let legacy: serde_json::Value = serde_json::from_str(&message.to_json()).unwrap();
+            assert_eq!(legacy[0], mask);
+
+            let event: serde_json::Value =
+                serde_json::from_str(&message.to_event().to_json()).unwrap();
+            assert_eq!(event["label"], mask);
+            assert_eq!(event["key"], "_Z");
+            assert_eq!(event["id"], "id\"\\");
+        }
+    }
+}
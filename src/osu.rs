@@ -0,0 +1,374 @@
+extern crate lzma_rs;
+
+use anyhow::Context;
+
+use crate::record::{Record, RecordBinding};
+
+// the last frame of newer replays holds the rng seed instead of input
+const SEED_FRAME_DELTA: i64 = -12345;
+
+// taiko, catch and mania frames use the same fields for other things
+const MODE_STANDARD: u8 = 0;
+
+// key bits of a replay frame, pressing K1 or K2 also sets the M1 or M2 bit
+const BIT_M1: u32 = 1;
+const BIT_M2: u32 = 2;
+const BIT_K1: u32 = 4;
+const BIT_K2: u32 = 8;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum OsuKey {
+    M1,
+    M2,
+    K1,
+    K2,
+}
+
+impl OsuKey {
+    pub const ALL: [OsuKey; 4] = [OsuKey::M1, OsuKey::M2, OsuKey::K1, OsuKey::K2];
+
+    fn is_held(&self, bits: u32) -> bool {
+        match self {
+            OsuKey::M1 => bits & BIT_M1 != 0 && bits & BIT_K1 == 0,
+            OsuKey::M2 => bits & BIT_M2 != 0 && bits & BIT_K2 == 0,
+            OsuKey::K1 => bits & BIT_K1 != 0,
+            OsuKey::K2 => bits & BIT_K2 != 0,
+        }
+    }
+}
+
+// the configured binding an osu! key drives
+#[derive(Clone)]
+pub struct OsuBinding {
+    pub input: String,
+    pub index: u32,
+    pub id: String,
+    pub label: String,
+}
+
+pub struct Frame {
+    pub time_ms: i64, // since the start of the replay
+    pub bits: u32,
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, count: usize) -> anyhow::Result<&'a [u8]> {
+        if self.data.len() < count {
+            anyhow::bail!("Replay ends unexpectedly");
+        }
+
+        let (bytes, rest) = self.data.split_at(count);
+        self.data = rest;
+
+        Ok(bytes)
+    }
+
+    fn skip(&mut self, count: usize) -> anyhow::Result<()> {
+        self.bytes(count).map(|_| ())
+    }
+
+    fn u32(&mut self) -> anyhow::Result<u32> {
+        Ok(u32::from_le_bytes(self.bytes(4)?.try_into()?))
+    }
+
+    fn uleb128(&mut self) -> anyhow::Result<usize> {
+        let mut value = 0_usize;
+        let mut shift = 0;
+
+        loop {
+            let byte = self.bytes(1)?[0];
+            value |= ((byte & 0x7F) as usize) << shift;
+
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+
+            shift += 7;
+
+            if shift >= usize::BITS {
+                anyhow::bail!("Replay string length is too long");
+            }
+        }
+    }
+
+    // 0x00 for no string, or 0x0b, a uleb128 length and utf-8
+    fn string(&mut self) -> anyhow::Result<String> {
+        match self.bytes(1)?[0] {
+            0x00 => Ok(String::new()),
+            0x0B => {
+                let len = self.uleb128()?;
+                Ok(String::from_utf8_lossy(self.bytes(len)?).to_string())
+            }
+
+            byte => anyhow::bail!("Invalid replay string marker {:#04x}", byte),
+        }
+    }
+}
+
+pub fn read_file(path: &str) -> anyhow::Result<Vec<Frame>> {
+    let file = std::fs::read(path).with_context(|| format!("Failed to read replay {}", path))?;
+    self::parse_replay(&file).with_context(|| format!("Failed to load replay {}", path))
+}
+
+fn parse_replay(data: &[u8]) -> anyhow::Result<Vec<Frame>> {
+    let mut reader = Reader { data };
+
+    let mode = reader.bytes(1)?[0];
+
+    if mode != MODE_STANDARD {
+        anyhow::bail!(
+            "Only osu!standard replays can be played back, not mode {}",
+            mode
+        );
+    }
+
+    // game version
+    reader.skip(4)?;
+
+    // beatmap hash, player name and replay hash
+    for _ in 0..3 {
+        reader.string()?;
+    }
+
+    // hit counts, score, max combo, perfect and mods
+    reader.skip(2 * 6 + 4 + 2 + 1 + 4)?;
+
+    // life bar graph and date
+    reader.string()?;
+    reader.skip(8)?;
+
+    let compressed_len = reader.u32()? as usize;
+    let mut compressed = reader.bytes(compressed_len)?;
+
+    let mut decompressed = vec![];
+    lzma_rs::lzma_decompress(&mut compressed, &mut decompressed)
+        .map_err(|err| anyhow::anyhow!("Failed to decompress replay: {:?}", err))?;
+
+    self::parse_frames(&String::from_utf8_lossy(&decompressed))
+}
+
+// "delta|x|y|keys" frames separated by commas, deltas are in ms since the previous frame
+fn parse_frames(data: &str) -> anyhow::Result<Vec<Frame>> {
+    let mut frames = vec![];
+    let mut time_ms = 0_i64;
+
+    for frame in data.split(',').filter(|frame| !frame.trim().is_empty()) {
+        let parts: Vec<&str> = frame.split('|').collect();
+
+        let (delta, bits) = match parts[..] {
+            [delta, _, _, bits] => (delta, bits),
+            _ => anyhow::bail!("Invalid replay frame \"{}\"", frame),
+        };
+
+        let delta = delta
+            .trim()
+            .parse::<i64>()
+            .with_context(|| format!("Invalid replay frame \"{}\"", frame))?;
+
+        if delta == SEED_FRAME_DELTA {
+            continue;
+        }
+
+        // some clients write keys as floats
+        let bits = bits
+            .trim()
+            .parse::<f64>()
+            .with_context(|| format!("Invalid replay frame \"{}\"", frame))?;
+
+        time_ms += delta;
+
+        frames.push(Frame {
+            time_ms,
+            bits: bits as u32,
+        });
+    }
+
+    Ok(frames)
+}
+
+// presses and releases of each key, bindings are given in OsuKey::ALL order
+// keys without a binding are left out
+pub fn to_records(frames: &[Frame], bindings: &[Option<OsuBinding>; 4]) -> Vec<Record> {
+    let mut records = vec![];
+    let mut previous = 0_u32;
+
+    // frames before the map starts can have negative times
+    let start_ms = frames.iter().map(|frame| frame.time_ms).min().unwrap_or(0);
+
+    for frame in frames {
+        for (key, binding) in OsuKey::ALL.iter().zip(bindings) {
+            let binding = match binding {
+                Some(binding) => binding,
+                None => continue,
+            };
+
+            let is_down = key.is_held(frame.bits);
+
+            if is_down == key.is_held(previous) {
+                continue;
+            }
+
+            let time_ms = frame.time_ms - start_ms;
+
+            records.push(Record {
+                input: binding.input.clone(),
+                is_down,
+                unix_ms: 0_f64,
+                monotonic_ns: time_ms as u64 * 1_000_000,
+                binding: Some(RecordBinding {
+                    index: binding.index,
+                    id: binding.id.clone(),
+                    label: binding.label.clone(),
+                }),
+            });
+        }
+
+        previous = frame.bits;
+    }
+
+    // negative deltas can put frames out of order
+    records.sort_by_key(|record| record.monotonic_ns);
+    records
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn binding(input: &str, index: u32) -> Option<OsuBinding> {
+        Some(OsuBinding {
+            input: input.to_string(),
+            index,
+            id: input.to_string(),
+            label: input.to_string(),
+        })
+    }
+
+    fn bindings() -> [Option<OsuBinding>; 4] {
+        [
+            binding("MouseLeft", 0),
+            binding("MouseRight", 1),
+            binding("Z", 2),
+            binding("X", 3),
+        ]
+    }
+
+    // input, is_down and ms for every record
+    fn records(frames: &str) -> Vec<(String, bool, u64)> {
+        let frames = self::parse_frames(frames).unwrap();
+
+        self::to_records(&frames, &bindings())
+            .into_iter()
+            .map(|record| {
+                (
+                    record.input,
+                    record.is_down,
+                    record.monotonic_ns / 1_000_000,
+                )
+            })
+            .collect()
+    }
+
+    // a replay with every field before the frames left empty
+    fn replay(mode: u8, frames: &str) -> Vec<u8> {
+        let mut compressed = vec![];
+        lzma_rs::lzma_compress(&mut frames.as_bytes(), &mut compressed).unwrap();
+
+        let mut data = vec![mode];
+        data.extend(20240101_u32.to_le_bytes());
+        data.extend([0x0B, 0x02, b'a', b'b']); // beatmap hash
+        data.push(0x00); // player name
+        data.push(0x00); // replay hash
+        data.extend([0_u8; 2 * 6 + 4 + 2 + 1 + 4]);
+        data.push(0x00); // life bar graph
+        data.extend([0_u8; 8]);
+        data.extend((compressed.len() as u32).to_le_bytes());
+        data.extend(compressed);
+
+        data
+    }
+
+    #[test]
+    fn frames_add_up_deltas() {
+        let frames = self::parse_frames("0|256|-500|0,-1|256|-500|0,20|1|2|1,15|1|2|0.0,").unwrap();
+        let times: Vec<i64> = frames.iter().map(|frame| frame.time_ms).collect();
+
+        assert_eq!(times, vec![0, -1, 19, 34]);
+        assert_eq!(frames[2].bits, BIT_M1);
+    }
+
+    #[test]
+    fn seed_frame_is_skipped() {
+        let frames = self::parse_frames("10|1|2|1,-12345|0|0|7382").unwrap();
+        assert_eq!(frames.len(), 1);
+    }
+
+    #[test]
+    fn bad_frames_are_errors() {
+        assert!(self::parse_frames("10|1|2").is_err());
+        assert!(self::parse_frames("ten|1|2|0").is_err());
+        assert!(self::parse_frames("10|1|2|keys").is_err());
+    }
+
+    #[test]
+    fn k1_hides_the_m1_bit_it_sets() {
+        // M1, then K1 (which sets M1 too), then back to M1 alone and up
+        let records = records("10|0|0|1,10|0|0|5,10|0|0|1,10|0|0|0");
+
+        assert_eq!(
+            records,
+            vec![
+                ("MouseLeft".to_string(), true, 0),
+                ("MouseLeft".to_string(), false, 10),
+                ("Z".to_string(), true, 10),
+                ("MouseLeft".to_string(), true, 20),
+                ("Z".to_string(), false, 20),
+                ("MouseLeft".to_string(), false, 30),
+            ]
+        );
+    }
+
+    #[test]
+    fn times_start_at_the_earliest_frame() {
+        // lead-in frames go negative before the map starts
+        let records = records("0|0|0|0,-500|0|0|0,100|0|0|8,50|0|0|0");
+
+        assert_eq!(
+            records,
+            vec![("X".to_string(), true, 100), ("X".to_string(), false, 150)]
+        );
+    }
+
+    #[test]
+    fn unbound_keys_are_left_out() {
+        let frames = self::parse_frames("10|0|0|2,10|0|0|0").unwrap();
+        let bindings = [binding("MouseLeft", 0), None, None, None];
+
+        assert!(self::to_records(&frames, &bindings).is_empty());
+    }
+
+    #[test]
+    fn standard_replays_are_read() {
+        let frames = self::parse_replay(&replay(0, "0|0|0|0,10|0|0|4,10|0|0|0")).unwrap();
+        let bits: Vec<u32> = frames.iter().map(|frame| frame.bits).collect();
+
+        assert_eq!(bits, vec![0, 4, 0]);
+    }
+
+    #[test]
+    fn other_modes_are_rejected() {
+        for mode in 1..=3 {
+            assert!(self::parse_replay(&replay(mode, "0|0|0|0")).is_err());
+        }
+    }
+
+    #[test]
+    fn truncated_replays_are_errors() {
+        let data = replay(0, "0|0|0|0");
+        assert!(self::parse_replay(&data[..data.len() - 4]).is_err());
+    }
+}
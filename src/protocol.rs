@@ -0,0 +1,96 @@
+extern crate serde;
+extern crate serde_json;
+
+use std::time::SystemTime;
+
+use serde::Serialize;
+
+use crate::input::Timestamp;
+
+// bumped when a field changes meaning or goes away, added fields and event types don't bump it
+pub const VERSION: u32 = 1;
+
+#[derive(Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum EventKind {
+    Key,
+    Reset,
+    Rates,
+    Held,
+    Paused,
+    Combo,
+    Typed,
+    Snapshot,
+    Config,
+}
+
+#[derive(Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    Down,
+    Up,
+}
+
+impl Direction {
+    pub fn of(is_down: bool) -> Self {
+        match is_down {
+            true => Direction::Down,
+            false => Direction::Up,
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct EventTime {
+    pub unix_ms: f64,
+    pub monotonic_ns: u64,
+}
+
+// every field is always present, null when it doesn't apply to the event type
+#[derive(Serialize)]
+pub struct Event {
+    pub version: u32,
+    #[serde(rename = "type")]
+    pub kind: EventKind,
+    pub key: Option<String>, // the bind, e.g. "^Z"
+    pub id: Option<String>,  // the binding's id, stays the same when bindings are reordered
+    pub index: Option<usize>,
+    pub label: Option<String>,
+    pub direction: Option<Direction>,
+    pub timestamp: EventTime,
+    pub payload: Option<serde_json::Value>,
+}
+
+impl Event {
+    pub fn new(kind: EventKind, time: Timestamp) -> Self {
+        Self {
+            version: VERSION,
+            kind,
+            key: None,
+            id: None,
+            index: None,
+            label: None,
+            direction: None,
+            timestamp: EventTime {
+                unix_ms: time.unix_ms(),
+                monotonic_ns: time.monotonic_ns,
+            },
+            payload: None,
+        }
+    }
+
+    pub fn now(kind: EventKind) -> Self {
+        Self::new(kind, Timestamp::new(SystemTime::now()))
+    }
+
+    pub fn with_payload(self, payload: impl Serialize) -> Self {
+        Self {
+            payload: serde_json::to_value(payload).ok(),
+            ..self
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap_or_default()
+    }
+}
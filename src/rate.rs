@@ -0,0 +1,158 @@
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use crate::input::Timestamp;
+
+#[derive(Clone, PartialEq, Serialize)]
+pub struct Rates {
+    pub kps: f64,
+    pub bpm: f64,
+    pub keys: Vec<f64>,     // per binding index
+    pub filtered: Vec<u64>, // chatter presses per binding index, filled in by the caller
+}
+
+impl Rates {
+    // rounded so tiny float differences don't count as a change
+    pub fn rounded(self) -> Self {
+        let hundredths = |rate: f64| (rate * 100_f64).round() / 100_f64;
+
+        Self {
+            kps: hundredths(self.kps),
+            bpm: self.bpm.round(),
+            keys: self.keys.into_iter().map(hundredths).collect(),
+            ..self
+        }
+    }
+}
+
+// rolling window of presses, each press remembers which bindings it triggered
+// presses go by the monotonic time of their event, not when they were handled
+pub struct RateWindow {
+    window: Duration,
+    presses: VecDeque<(u64, Vec<usize>)>,
+}
+
+impl RateWindow {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            presses: VecDeque::new(),
+        }
+    }
+
+    pub fn set_window(&mut self, window: Duration) {
+        self.window = window;
+    }
+
+    pub fn push(&mut self, time: &Timestamp, indices: Vec<usize>) {
+        self.presses.push_back((time.monotonic_ns, indices));
+    }
+
+    fn prune(&mut self, now: &Timestamp) {
+        let window_ns = self.window.as_nanos() as u64;
+
+        while let Some((time_ns, _)) = self.presses.front() {
+            if now.monotonic_ns.saturating_sub(*time_ns) <= window_ns {
+                break;
+            }
+
+            self.presses.pop_front();
+        }
+    }
+
+    pub fn rates(&mut self, now: &Timestamp, key_count: usize) -> Rates {
+        self.prune(now);
+
+        let seconds = self.window.as_secs_f64();
+        let mut keys = vec![0_f64; key_count];
+
+        for (_, indices) in &self.presses {
+            for i in indices.iter().filter(|i| **i < key_count) {
+                keys[*i] += 1_f64 / seconds;
+            }
+        }
+
+        Rates {
+            kps: self.presses.len() as f64 / seconds,
+            bpm: self.bpm(),
+            keys,
+            filtered: vec![],
+        }
+    }
+
+    // tapping bpm assumes 1/4 streams, i.e. four presses per beat
+    fn bpm(&self) -> f64 {
+        let (first, last) = match (self.presses.front(), self.presses.back()) {
+            (Some(first), Some(last)) => (first.0, last.0),
+            _ => return 0_f64,
+        };
+
+        let span = Duration::from_nanos(last.saturating_sub(first)).as_secs_f64();
+
+        if self.presses.len() < 2 || span <= 0_f64 {
+            return 0_f64;
+        }
+
+        let interval = span / (self.presses.len() - 1) as f64;
+        60_f64 / (interval * 4_f64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::SystemTime;
+
+    use super::*;
+
+    fn at(ms: u64) -> Timestamp {
+        Timestamp {
+            system: SystemTime::UNIX_EPOCH,
+            monotonic_ns: ms * 1_000_000,
+        }
+    }
+
+    #[test]
+    fn rates_cover_the_window_and_nothing_older() {
+        let mut window = RateWindow::new(Duration::from_secs(1));
+
+        assert_eq!(window.rates(&at(0), 2).kps, 0_f64);
+        assert_eq!(window.rates(&at(0), 2).bpm, 0_f64);
+
+        window.push(&at(0), vec![0]);
+        window.push(&at(100), vec![1]);
+        window.push(&at(500), vec![0, 1, 5]); // past the last binding, left out of keys
+
+        // the first press is exactly a window old
+        let rates = window.rates(&at(1000), 2);
+        assert_eq!(rates.kps, 3_f64);
+        assert_eq!(rates.keys, vec![2_f64, 2_f64]);
+        assert_eq!(rates.bpm, 60_f64); // a press every 250ms, four to a beat
+
+        let rates = window.rates(&at(1001), 2);
+        assert_eq!(rates.kps, 2_f64);
+        assert_eq!(rates.keys, vec![1_f64, 2_f64]);
+        assert_eq!(rates.bpm, 37.5_f64);
+
+        // one press left has no interval to go by
+        let rates = window.rates(&at(1101), 2);
+        assert_eq!(rates.kps, 1_f64);
+        assert_eq!(rates.bpm, 0_f64);
+
+        let rates = window.rates(&at(1501), 2);
+        assert_eq!(rates.kps, 0_f64);
+        assert_eq!(rates.keys, vec![0_f64, 0_f64]);
+    }
+
+    #[test]
+    fn rates_are_per_second_of_the_window() {
+        let mut window = RateWindow::new(Duration::from_millis(500));
+
+        window.push(&at(0), vec![0]);
+        window.push(&at(250), vec![0]);
+
+        let rates = window.rates(&at(500), 1);
+        assert_eq!(rates.kps, 4_f64);
+        assert_eq!(rates.keys, vec![4_f64]);
+    }
+}
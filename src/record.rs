@@ -0,0 +1,474 @@
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::Context;
+
+// recordings start with the magic and a version, followed by records back to back
+// every number is little endian, strings are a u16 length followed by utf-8
+//
+// record: u64 monotonic ns, f64 unix ms, u8 flags, [u32 binding index], input, [label, id]
+pub const MAGIC: &[u8; 4] = b"KORC";
+pub const VERSION: u16 = 1;
+pub const EXTENSION: &str = "korc";
+
+const FLAG_DOWN: u8 = 1;
+const FLAG_BINDING: u8 = 2;
+
+// big enough that whatever's pending goes out in one write
+const WRITE_BUFFER: usize = 64 * 1024;
+
+// the time in the last file name given out, names never repeat it
+static LAST_NAMED_MS: AtomicU64 = AtomicU64::new(0);
+
+// a single input event, along with the binding it triggered if any
+#[derive(Clone, PartialEq, Debug)]
+pub struct Record {
+    pub input: String,
+    pub is_down: bool,
+    pub unix_ms: f64,
+    pub monotonic_ns: u64,
+    pub binding: Option<RecordBinding>,
+}
+
+// the binding a record triggered
+#[derive(Clone, PartialEq, Debug)]
+pub struct RecordBinding {
+    pub index: u32,
+    pub id: String,
+    pub label: String,
+}
+
+impl Record {
+    fn write_to(&self, writer: &mut impl Write) -> std::io::Result<()> {
+        let mut flags = 0;
+
+        if self.is_down {
+            flags |= FLAG_DOWN;
+        }
+
+        if self.binding.is_some() {
+            flags |= FLAG_BINDING;
+        }
+
+        writer.write_all(&self.monotonic_ns.to_le_bytes())?;
+        writer.write_all(&self.unix_ms.to_le_bytes())?;
+        writer.write_all(&[flags])?;
+
+        if let Some(binding) = &self.binding {
+            writer.write_all(&binding.index.to_le_bytes())?;
+        }
+
+        self::write_str(writer, &self.input)?;
+
+        if let Some(binding) = &self.binding {
+            self::write_str(writer, &binding.label)?;
+            self::write_str(writer, &binding.id)?;
+        }
+
+        Ok(())
+    }
+}
+
+fn write_str(writer: &mut impl Write, str: &str) -> std::io::Result<()> {
+    let bytes = &str.as_bytes()[..str.len().min(u16::MAX as usize)];
+
+    writer.write_all(&(bytes.len() as u16).to_le_bytes())?;
+    writer.write_all(bytes)
+}
+
+fn encode(records: impl IntoIterator<Item = Record>) -> Vec<u8> {
+    let mut bytes = vec![];
+
+    for record in records {
+        // writing to a vec can't fail
+        let _ = record.write_to(&mut bytes);
+    }
+
+    bytes
+}
+
+fn create(path: &str) -> anyhow::Result<BufWriter<File>> {
+    if let Some(parent) = std::path::Path::new(path).parent() {
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create recording directory for {}", path))?;
+    }
+
+    // never truncates an earlier recording, even if the clock went back
+    let file = File::options()
+        .write(true)
+        .create_new(true)
+        .open(path)
+        .with_context(|| format!("Failed to create recording {}", path))?;
+
+    let mut writer = BufWriter::with_capacity(WRITE_BUFFER, file);
+
+    writer.write_all(MAGIC)?;
+    writer.write_all(&VERSION.to_le_bytes())?;
+
+    Ok(writer)
+}
+
+// "<directory>/<kind>-<unix ms>.korc", names given out in the same millisecond
+// are moved a millisecond on so no two are the same
+pub fn file_name(directory: &str, kind: &str) -> String {
+    let now_ms = match SystemTime::now().duration_since(UNIX_EPOCH) {
+        Ok(duration) => duration.as_millis() as u64,
+        Err(_) => 0,
+    };
+
+    let last_ms = LAST_NAMED_MS
+        .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |last_ms| {
+            Some(now_ms.max(last_ms + 1))
+        })
+        .unwrap_or_default();
+
+    let ms = now_ms.max(last_ms + 1);
+
+    format!("{}/{}-{}.{}", directory, kind, ms, EXTENSION)
+}
+
+// collects the session while recording, and always keeps the last few seconds around
+// nothing here touches the disk, the bytes are handed to a Writer
+pub struct Recorder {
+    session: Option<String>,
+    pending: Vec<u8>, // encoded records the writer hasn't been given yet
+
+    buffer: VecDeque<Record>,
+    buffer_window: Duration,
+}
+
+impl Recorder {
+    pub fn new(buffer_window: Duration) -> Self {
+        Self {
+            session: None,
+            pending: vec![],
+
+            buffer: VecDeque::new(),
+            buffer_window,
+        }
+    }
+
+    pub fn set_buffer_window(&mut self, buffer_window: Duration) {
+        self.buffer_window = buffer_window;
+    }
+
+    pub fn recording_path(&self) -> Option<String> {
+        self.session.clone()
+    }
+
+    pub fn start(&mut self, path: String) -> anyhow::Result<()> {
+        if self.session.is_some() {
+            anyhow::bail!("Already recording");
+        }
+
+        self.session = Some(path);
+        self.pending.clear();
+
+        Ok(())
+    }
+
+    // the path of the finished recording and what's left to write to it
+    pub fn stop(&mut self) -> Option<(String, Vec<u8>)> {
+        let path = self.session.take()?;
+        Some((path, std::mem::take(&mut self.pending)))
+    }
+
+    // what's been recorded since last time, taken on a timer so a crash only loses
+    // the last moments of a recording
+    pub fn take_pending(&mut self) -> Option<(String, Vec<u8>)> {
+        match &self.session {
+            Some(path) if !self.pending.is_empty() => {
+                Some((path.clone(), std::mem::take(&mut self.pending)))
+            }
+
+            _ => None,
+        }
+    }
+
+    // the writer couldn't keep the recording going
+    pub fn abandon(&mut self, path: &str) {
+        if self.session.as_deref() == Some(path) {
+            self.session = None;
+            self.pending.clear();
+        }
+    }
+
+    // only buffers, this runs on the input hook and can't wait on the disk
+    pub fn push(&mut self, record: Record) {
+        let window_ns = self.buffer_window.as_nanos() as u64;
+
+        while let Some(front) = self.buffer.front() {
+            if record.monotonic_ns.saturating_sub(front.monotonic_ns) <= window_ns {
+                break;
+            }
+
+            self.buffer.pop_front();
+        }
+
+        if self.session.is_some() {
+            let _ = record.write_to(&mut self.pending);
+        }
+
+        self.buffer.push_back(record);
+    }
+
+    // the last few seconds, ready to be saved with write_replay
+    pub fn replay(&self) -> Vec<u8> {
+        self::encode(self.buffer.iter().cloned())
+    }
+}
+
+// the disk side of a recording session, only one is open at a time
+#[derive(Default)]
+pub struct Writer {
+    session: Option<(String, BufWriter<File>)>,
+}
+
+impl Writer {
+    pub fn start(&mut self, path: &str) -> anyhow::Result<()> {
+        self.session = Some((path.to_string(), self::create(path)?));
+        Ok(())
+    }
+
+    // the session is closed if it can't be written to
+    pub fn write(&mut self, path: &str, bytes: &[u8]) -> anyhow::Result<()> {
+        let writer = match &mut self.session {
+            Some((session, writer)) if session == path => writer,
+            _ => anyhow::bail!("Recording {} isn't open", path),
+        };
+
+        let result = writer.write_all(bytes).and_then(|_| writer.flush());
+
+        if let Err(err) = result {
+            self.session = None;
+            anyhow::bail!("Failed to write to recording {}: {:?}", path, err);
+        }
+
+        Ok(())
+    }
+
+    pub fn stop(&mut self, path: &str, bytes: &[u8]) -> anyhow::Result<()> {
+        self.write(path, bytes)
+            .with_context(|| format!("Failed to finish recording {}", path))?;
+
+        self.session = None;
+
+        Ok(())
+    }
+}
+
+pub fn write_replay(path: &str, bytes: &[u8]) -> anyhow::Result<()> {
+    let mut writer = self::create(path)?;
+
+    writer
+        .write_all(bytes)
+        .and_then(|_| writer.flush())
+        .with_context(|| format!("Failed to write replay {}", path))?;
+
+    Ok(())
+}
+
+fn read_bytes<'a>(data: &mut &'a [u8], count: usize) -> anyhow::Result<&'a [u8]> {
+    if data.len() < count {
+        anyhow::bail!("Recording ends in the middle of a record");
+    }
+
+    let (bytes, rest) = data.split_at(count);
+    *data = rest;
+
+    Ok(bytes)
+}
+
+fn read_u64(data: &mut &[u8]) -> anyhow::Result<u64> {
+    Ok(u64::from_le_bytes(self::read_bytes(data, 8)?.try_into()?))
+}
+
+fn read_str(data: &mut &[u8]) -> anyhow::Result<String> {
+    let len = u16::from_le_bytes(self::read_bytes(data, 2)?.try_into()?) as usize;
+    let bytes = self::read_bytes(data, len)?;
+
+    Ok(String::from_utf8_lossy(bytes).to_string())
+}
+
+pub fn read_file(path: &str) -> anyhow::Result<Vec<Record>> {
+    let file = std::fs::read(path).with_context(|| format!("Failed to read recording {}", path))?;
+    let mut data = file.as_slice();
+
+    if self::read_bytes(&mut data, MAGIC.len())? != MAGIC {
+        anyhow::bail!("{} is not a recording", path);
+    }
+
+    let version = u16::from_le_bytes(self::read_bytes(&mut data, 2)?.try_into()?);
+
+    if version != VERSION {
+        anyhow::bail!(
+            "{} is a version {} recording, expected {}",
+            path,
+            version,
+            VERSION
+        );
+    }
+
+    let mut records = vec![];
+
+    while !data.is_empty() {
+        let monotonic_ns = self::read_u64(&mut data)?;
+        let unix_ms = f64::from_bits(self::read_u64(&mut data)?);
+        let flags = self::read_bytes(&mut data, 1)?[0];
+
+        let index = if flags & FLAG_BINDING != 0 {
+            Some(u32::from_le_bytes(
+                self::read_bytes(&mut data, 4)?.try_into()?,
+            ))
+        } else {
+            None
+        };
+
+        let input = self::read_str(&mut data)?;
+
+        let binding = match index {
+            Some(index) => {
+                let label = self::read_str(&mut data)?;
+                let id = self::read_str(&mut data)?;
+
+                Some(RecordBinding { index, id, label })
+            }
+
+            None => None,
+        };
+
+        records.push(Record {
+            input,
+            is_down: flags & FLAG_DOWN != 0,
+            unix_ms,
+            monotonic_ns,
+            binding,
+        });
+    }
+
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> String {
+        let directory =
+            std::env::temp_dir().join(format!("keyoverlay-test-{}", std::process::id()));
+        directory.join(name).to_string_lossy().to_string()
+    }
+
+    fn record(ms: u64, is_down: bool, binding: Option<(u32, &str)>) -> Record {
+        Record {
+            input: "Z".to_string(),
+            is_down,
+            unix_ms: 1_700_000_000_000.5 + ms as f64,
+            monotonic_ns: ms * 1_000_000,
+            binding: binding.map(|(index, label)| RecordBinding {
+                index,
+                id: format!("binding-{}", index),
+                label: label.to_string(),
+            }),
+        }
+    }
+
+    #[test]
+    fn sessions_read_back_the_same() {
+        let path = temp_path("session.korc");
+        let records = vec![
+            record(0, true, Some((0, "Z"))),
+            record(25, false, Some((0, "Z"))),
+            record(30, true, None),
+            record(40, false, Some((7, "ラベル"))),
+        ];
+
+        let mut recorder = Recorder::new(Duration::from_secs(30));
+        let mut writer = Writer::default();
+
+        recorder.start(path.clone()).unwrap();
+        writer.start(&path).unwrap();
+
+        for (i, record) in records.iter().enumerate() {
+            recorder.push(record.clone());
+
+            // some of it goes out mid-session like it does on the timer
+            if i == 1 {
+                let (pending_path, bytes) = recorder.take_pending().unwrap();
+                writer.write(&pending_path, &bytes).unwrap();
+            }
+        }
+
+        let (stopped_path, bytes) = recorder.stop().unwrap();
+        assert_eq!(stopped_path, path);
+        writer.stop(&path, &bytes).unwrap();
+
+        assert_eq!(self::read_file(&path).unwrap(), records);
+        assert!(recorder.stop().is_none());
+
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn replay_buffer_keeps_its_window() {
+        let path = temp_path("replay.korc");
+
+        let mut recorder = Recorder::new(Duration::from_millis(100));
+
+        for ms in [0, 50, 120, 200] {
+            recorder.push(record(ms, true, None));
+        }
+
+        self::write_replay(&path, &recorder.replay()).unwrap();
+
+        let times: Vec<u64> = self::read_file(&path)
+            .unwrap()
+            .iter()
+            .map(|record| record.monotonic_ns / 1_000_000)
+            .collect();
+
+        assert_eq!(times, vec![120, 200]);
+
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn file_names_never_repeat() {
+        let names: Vec<String> = (0..100).map(|_| self::file_name("dir", "replay")).collect();
+
+        for (i, name) in names.iter().enumerate() {
+            assert!(!names[i + 1..].contains(name));
+        }
+
+        let path = temp_path("existing.korc");
+        self::write_replay(&path, &[]).unwrap();
+        assert!(self::write_replay(&path, &[]).is_err());
+
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn other_versions_and_partial_records_are_errors() {
+        let path = temp_path("broken.korc");
+        std::fs::create_dir_all(std::path::Path::new(&path).parent().unwrap()).unwrap();
+
+        let mut data = MAGIC.to_vec();
+        data.extend((VERSION + 1).to_le_bytes());
+        std::fs::write(&path, &data).unwrap();
+        assert!(self::read_file(&path).is_err());
+
+        let mut data = MAGIC.to_vec();
+        data.extend(VERSION.to_le_bytes());
+        data.extend(5_u64.to_le_bytes());
+        std::fs::write(&path, &data).unwrap();
+        assert!(self::read_file(&path).is_err());
+
+        std::fs::write(&path, b"not a recording").unwrap();
+        assert!(self::read_file(&path).is_err());
+
+        let _ = std::fs::remove_file(path);
+    }
+}
@@ -1,16 +1,30 @@
-extern crate egui_keybinds;
 extern crate serde;
 
-use crate::helper;
+use crate::{
+    helper,
+    input::{Bind, Device},
+    key::KeyBind,
+};
 use anyhow::Context;
-use egui_keybinds::KeyBind;
 use serde::{Deserialize, Serialize};
 
-#[derive(Serialize, Deserialize, Clone, Hash)]
+#[derive(Serialize, Deserialize, Clone, Hash, Default)]
 pub struct Settings {
     pub server: ServerSettings,
     pub web: WebSettings,
     pub keyboard: KeyboardSettings,
+
+    #[serde(default)]
+    pub input: InputSettings,
+
+    #[serde(default)]
+    pub recording: RecordingSettings,
+
+    #[serde(default)]
+    pub osu: OsuSettings,
+
+    #[serde(default)]
+    pub privacy: PrivacySettings,
 }
 
 #[derive(Serialize, Deserialize, Clone, Hash)]
@@ -20,15 +34,107 @@ pub struct ServerSettings {
 }
 
 #[derive(Serialize, Deserialize, Clone, Hash)]
+#[serde(default)]
 pub struct WebSettings {
-    pub websocket_endpoint: String,
+    pub websocket_endpoint: String, // the original array messages, for older presets
+    pub events_endpoint: String,    // versioned json events
     pub local_file_path: String,
 }
 
+// scancode mode reads key names as positions on a us qwerty layout, whatever the actual layout
+#[derive(Serialize, Deserialize, Clone, Copy, Hash, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum BindingMode {
+    #[default]
+    Name,
+    Scancode,
+}
+
 #[derive(Serialize, Deserialize, Clone, Hash)]
+#[serde(default)]
 pub struct KeyboardSettings {
-    pub keys: Vec<String>,
+    pub keys: Vec<KeySettings>,
     pub reset: String,
+    pub record: String,      // starts and stops a recording
+    pub save_replay: String, // saves the replay buffer
+    pub pause: String,       // pauses and resumes sending input to clients
+    pub kps_window_ms: u64,
+    pub binding_mode: BindingMode,
+    // presses this soon after a release are switch chatter, 0 turns filtering off
+    pub debounce_ms: u64,
+    // held keys are released after this when the backend can't check them, 0 never releases.
+    // off by default, rdev asks the x server or windows and evdev reads the key state itself,
+    // a timeout would let go of keys really held down where neither works
+    pub stuck_timeout_ms: u64,
+    // every key press is shown as a combo or typed text, for tutorials
+    pub capture_all: bool,
+    // unbound keys share a slot after the last binding
+    pub catch_all: bool,
+}
+
+// keys used to be "bind:mask" strings, those are still read and given an id on load
+#[derive(Serialize, Deserialize, Clone, Hash)]
+#[serde(untagged)]
+pub enum KeySettings {
+    Short(String),
+    Full {
+        // generated once and sent to clients, so overlays don't depend on list order
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        id: Option<String>,
+        bind: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        mask: Option<String>,
+        // only react to this device, matched against its id or name
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        device: Option<String>,
+        // overrides the keyboard wide debounce_ms
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        debounce_ms: Option<u64>,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Hash, PartialEq, Eq, Default, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    #[default]
+    Rdev,
+    Script,
+    Evdev,
+}
+
+#[derive(Serialize, Deserialize, Clone, Hash)]
+#[serde(default)]
+pub struct RecordingSettings {
+    pub directory: String,
+    pub replay_seconds: u64, // how much the replay buffer holds
+}
+
+// binds that osu! replay keys are played back as
+#[derive(Serialize, Deserialize, Clone, Hash)]
+#[serde(default)]
+pub struct OsuSettings {
+    pub m1: String,
+    pub m2: String,
+    pub k1: String,
+    pub k2: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Hash, Default)]
+#[serde(default)]
+pub struct PrivacySettings {
+    // input isn't sent while the focused window's title or class contains one of these
+    pub auto_pause: Vec<String>,
+    // presses are sent without saying which key they were
+    pub redact: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Hash)]
+#[serde(default)]
+pub struct InputSettings {
+    pub backend: Backend,
+    pub script_path: String,
+    // evdev device names or paths to read, every key device when empty
+    pub evdev_devices: Vec<String>,
 }
 
 impl Default for ServerSettings {
@@ -44,6 +150,7 @@ impl Default for WebSettings {
     fn default() -> Self {
         Self {
             websocket_endpoint: "/ws".to_string(),
+            events_endpoint: "/events".to_string(),
             local_file_path: "/static".to_string(),
         }
     }
@@ -52,58 +159,159 @@ impl Default for WebSettings {
 impl Default for KeyboardSettings {
     fn default() -> Self {
         Self {
-            keys: vec!["Z".to_string(), "X".to_string()],
+            keys: vec![
+                KeySettings::Short("Z".to_string()),
+                KeySettings::Short("X".to_string()),
+            ],
             reset: "End".to_string(),
+            record: KeyBind::empty().serialize(),
+            save_replay: KeyBind::empty().serialize(),
+            pause: KeyBind::empty().serialize(),
+            kps_window_ms: 1000,
+            binding_mode: BindingMode::default(),
+            debounce_ms: 0,
+            stuck_timeout_ms: 0,
+            capture_all: false,
+            catch_all: false,
         }
     }
 }
 
-impl Default for Settings {
+impl Default for InputSettings {
     fn default() -> Self {
         Self {
-            server: Default::default(),
-            web: Default::default(),
-            keyboard: Default::default(),
+            backend: Backend::default(),
+            script_path: "input.script".to_string(),
+            evdev_devices: vec![],
         }
     }
 }
 
-impl From<OverlaySettings> for Settings {
-    fn from(mut overlay_settings: OverlaySettings) -> Self {
-        let mut keys = vec![];
+impl Default for RecordingSettings {
+    fn default() -> Self {
+        Self {
+            directory: "recordings".to_string(),
+            replay_seconds: 30,
+        }
+    }
+}
 
-        for mut pair in overlay_settings.keys {
-            let mut serialized = pair.0.serialize();
+impl Default for OsuSettings {
+    fn default() -> Self {
+        Self {
+            m1: "MouseLeft".to_string(),
+            m2: "MouseRight".to_string(),
+            k1: "Z".to_string(),
+            k2: "X".to_string(),
+        }
+    }
+}
 
-            if let Some(mask) = pair.1 {
-                serialized.push(':');
-                serialized.push_str(&mask);
-            }
+impl From<OverlaySettings> for Settings {
+    fn from(overlay_settings: OverlaySettings) -> Self {
+        let mut keys = vec![];
 
-            keys.push(serialized);
+        // the short form has nowhere to keep the id
+        for entry in overlay_settings.keys {
+            keys.push(KeySettings::Full {
+                id: Some(entry.id),
+                bind: entry.bind.serialize(),
+                mask: entry.mask,
+                device: entry.device,
+                debounce_ms: entry.debounce_ms,
+            });
         }
 
         Self {
             server: overlay_settings.server,
             web: overlay_settings.web,
+            input: overlay_settings.input,
+            recording: overlay_settings.recording,
+            osu: overlay_settings.osu,
+            privacy: overlay_settings.privacy,
 
             keyboard: KeyboardSettings {
                 keys,
                 reset: overlay_settings.reset.serialize(),
+                record: overlay_settings.record.serialize(),
+                save_replay: overlay_settings.save_replay.serialize(),
+                pause: overlay_settings.pause.serialize(),
+                ..overlay_settings.toml_settings.keyboard
             },
         }
     }
 }
 
+fn split_short(str: &str) -> anyhow::Result<(String, Option<String>)> {
+    if !str.contains(":") {
+        return Ok((str.to_owned(), None));
+    }
+
+    let split: Vec<String> = str.split(":").map(|s| s.to_string()).collect();
+
+    let first = split
+        .first()
+        .with_context(|| "Failed to get first in split list")?
+        .to_owned();
+
+    let last = split
+        .last()
+        .with_context(|| "Failed to get last in split list")?
+        .to_owned();
+
+    Ok((first, Some(last)))
+}
+
 // Actual settings
 
+#[derive(Clone, Hash)]
+pub struct KeyEntry {
+    pub id: String,
+    pub bind: Bind,
+    pub mask: Option<String>,
+    pub device: Option<String>,
+    pub debounce_ms: Option<u64>,
+}
+
+impl KeyEntry {
+    pub fn new(bind: Bind, mask: Option<String>, device: Option<String>) -> Self {
+        Self {
+            id: helper::new_id(),
+            bind,
+            mask,
+            device,
+            debounce_ms: None,
+        }
+    }
+
+    pub fn empty() -> Self {
+        Self::new(Bind::empty(), None, None)
+    }
+
+    // unrestricted entries take input from any device
+    pub fn accepts(&self, device: Option<&Device>) -> bool {
+        match (&self.device, device) {
+            (None, _) => true,
+            (Some(filter), Some(device)) => device.matches(filter),
+            (Some(_), None) => false,
+        }
+    }
+}
+
 #[derive(Clone, Hash)]
 pub struct OverlaySettings {
-    pub keys: Vec<(KeyBind, Option<String>)>,
+    pub keys: Vec<KeyEntry>,
     pub reset: KeyBind,
+    pub record: KeyBind,
+    pub save_replay: KeyBind,
+    pub pause: KeyBind,
 
     pub server: ServerSettings,
     pub web: WebSettings,
+    pub input: InputSettings,
+    pub recording: RecordingSettings,
+    pub osu: OsuSettings,
+    pub privacy: PrivacySettings,
 
     pub toml_settings: Settings,
 }
@@ -112,58 +320,138 @@ impl OverlaySettings {
     pub fn is_fatal_change(one: &Self, two: &Self) -> bool {
         helper::hash_of(&one.server) != helper::hash_of(&two.server)
             || helper::hash_of(&one.web) != helper::hash_of(&two.web)
+            || helper::hash_of(&one.input) != helper::hash_of(&two.input)
     }
 
     pub fn to_toml(&self) -> anyhow::Result<String> {
         let new_toml_settings = Settings::from(self.clone());
 
         match toml::to_string_pretty(&new_toml_settings) {
-            Ok(toml) => return Ok(toml),
+            Ok(toml) => Ok(toml),
             Err(error) => anyhow::bail!("{:?}", error),
-        };
+        }
     }
 
-    pub fn from_toml(toml_settings: Settings) -> anyhow::Result<Self> {
-        let mut keys: Vec<(KeyBind, Option<String>)> = vec![];
-
-        for str in &toml_settings.keyboard.keys {
-            let data: (String, Option<String>) = if str.contains(":") {
-                let split: Vec<String> = str.split(":").map(|s| s.to_string()).collect();
+    // also says whether any key was given a new id, which the file doesn't have yet
+    pub fn from_toml(toml_settings: Settings) -> anyhow::Result<(Self, bool)> {
+        let mut keys: Vec<KeyEntry> = vec![];
+        let mut ids_changed = false;
+
+        for key_settings in &toml_settings.keyboard.keys {
+            let (id, bind, mask, device, debounce_ms) = match key_settings {
+                KeySettings::Short(str) => {
+                    let (bind, mask) = self::split_short(str)?;
+                    (None, bind, mask, None, None)
+                }
+
+                KeySettings::Full {
+                    id,
+                    bind,
+                    mask,
+                    device,
+                    debounce_ms,
+                } => (
+                    id.to_owned(),
+                    bind.to_owned(),
+                    mask.to_owned(),
+                    device.to_owned(),
+                    *debounce_ms,
+                ),
+            };
 
-                let first = split
-                    .first()
-                    .with_context(|| "Failed to get first in split list")?
-                    .to_owned();
+            let key = Bind::deserialize(&bind).unwrap_or(Bind::empty());
+            let entry = KeyEntry::new(key, mask, device);
 
-                let last = split
-                    .last()
-                    .with_context(|| "Failed to get last in split list")?
-                    .to_owned();
+            // copied entries would share an id, the copy gets a new one
+            let id = id.filter(|id| !id.is_empty() && keys.iter().all(|key| key.id != *id));
+            ids_changed |= id.is_none();
 
-                (first, Some(last))
-            } else {
-                (str.to_owned(), None)
-            };
+            let id = id.unwrap_or(entry.id);
 
-            let key = KeyBind::deserialize(data.0).unwrap_or(KeyBind::empty());
-
-            keys.push((key, data.1));
+            keys.push(KeyEntry {
+                id,
+                debounce_ms,
+                ..entry
+            });
         }
 
-        let mut reset = KeyBind::empty();
+        let control = |data: &str| KeyBind::deserialize(data).unwrap_or(KeyBind::empty());
 
-        if let Ok(key) = KeyBind::deserialize(toml_settings.keyboard.reset.clone()) {
-            reset = key;
-        }
+        let reset = control(&toml_settings.keyboard.reset);
+        let record = control(&toml_settings.keyboard.record);
+        let save_replay = control(&toml_settings.keyboard.save_replay);
+        let pause = control(&toml_settings.keyboard.pause);
 
-        Ok(Self {
+        let settings = Self {
             keys,
             reset,
+            record,
+            save_replay,
+            pause,
 
             server: toml_settings.server.clone(),
             web: toml_settings.web.clone(),
+            input: toml_settings.input.clone(),
+            recording: toml_settings.recording.clone(),
+            osu: toml_settings.osu.clone(),
+            privacy: toml_settings.privacy.clone(),
 
             toml_settings,
-        })
+        };
+
+        Ok((settings, ids_changed))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn with_keys(keys: Vec<KeySettings>) -> Settings {
+        let mut settings = Settings::default();
+        settings.keyboard.keys = keys;
+        settings
+    }
+
+    fn full(id: Option<&str>, bind: &str) -> KeySettings {
+        KeySettings::Full {
+            id: id.map(|id| id.to_string()),
+            bind: bind.to_string(),
+            mask: None,
+            device: None,
+            debounce_ms: None,
+        }
+    }
+
+    #[test]
+    fn made_up_ids_are_reported() {
+        let kept = self::with_keys(vec![full(Some("a"), "Z"), full(Some("b"), "X")]);
+        let (settings, ids_changed) = OverlaySettings::from_toml(kept).unwrap();
+        assert!(!ids_changed);
+        assert_eq!(settings.keys[1].id, "b");
+
+        let changed = [
+            vec![KeySettings::Short("Z:zed".to_string())],
+            vec![full(None, "Z")],
+            vec![full(Some(""), "Z")],
+            vec![full(Some("a"), "Z"), full(Some("a"), "X")],
+        ];
+
+        for keys in changed {
+            let (settings, ids_changed) =
+                OverlaySettings::from_toml(self::with_keys(keys)).unwrap();
+            assert!(ids_changed);
+
+            // written back, the same ids come out the next time
+            let toml_settings = toml::from_str::<Settings>(&settings.to_toml().unwrap()).unwrap();
+            let (reloaded, ids_changed) = OverlaySettings::from_toml(toml_settings).unwrap();
+            assert!(!ids_changed);
+
+            let ids = |settings: &OverlaySettings| -> Vec<String> {
+                settings.keys.iter().map(|key| key.id.clone()).collect()
+            };
+
+            assert_eq!(ids(&reloaded), ids(&settings));
+        }
     }
 }
@@ -0,0 +1,732 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    combo, helper,
+    input::{self, Input, InputEvent, Timestamp},
+    key::{Key, KeyBind, Modifier},
+    settings::{BindingMode, KeyEntry},
+};
+
+// hotkeys that drive the overlay itself instead of being shown
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Control {
+    Reset,
+    Record,
+    SaveReplay,
+    Pause,
+}
+
+// what bindings are looked up by, in scancode mode keys go by where they physically are
+#[derive(PartialEq, Eq, Hash)]
+enum LookupKey {
+    Input(Input),
+    Scancode(u32),
+}
+
+// a binding going down or up
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Transition {
+    pub index: usize,
+    pub is_down: bool,
+    pub hold_ns: Option<u64>, // releases only
+}
+
+#[derive(PartialEq, Debug)]
+pub enum Outcome {
+    // a control bind went down, it runs once released
+    Held,
+    Control(Control),
+    // transitions are in binding order, filtered holds bindings whose press was chatter
+    // catch_all is an unbound key in the slot after the last binding, with its label
+    Bindings {
+        transitions: Vec<Transition>,
+        filtered: Vec<usize>,
+        catch_all: Option<(Transition, String)>,
+    },
+}
+
+// everything an input event reads or changes, kept behind one lock so events apply in order
+pub struct KeyboardState {
+    keys: Vec<KeyEntry>,
+    controls: Vec<(Control, KeyBind)>,
+    binding_mode: BindingMode,
+    debounce_ms: u64,
+
+    // binding indices by what they listen to, rebuilt whenever keys or the mode change
+    lookup: HashMap<LookupKey, Vec<usize>>,
+
+    held_keys: HashMap<usize, Timestamp>, // when each held binding was pressed
+    held_modifiers: Vec<Key>,
+    held_control: Option<Control>,
+
+    // chatter filtering: when each binding was last let go, and presses that were filtered
+    // so their releases can be dropped too
+    last_release: HashMap<usize, u64>,
+    debounced: HashSet<usize>,
+
+    // unbound keys held in the catch-all slot, with the label they were pressed as
+    catch_all: bool,
+    catch_all_held: HashMap<Key, (Timestamp, String)>,
+}
+
+impl KeyboardState {
+    pub fn new() -> Self {
+        Self {
+            keys: vec![],
+            controls: vec![],
+            binding_mode: BindingMode::default(),
+            debounce_ms: 0,
+
+            lookup: HashMap::new(),
+
+            held_keys: HashMap::new(),
+            held_modifiers: vec![],
+            held_control: None,
+
+            last_release: HashMap::new(),
+            debounced: HashSet::new(),
+
+            catch_all: false,
+            catch_all_held: HashMap::new(),
+        }
+    }
+
+    pub fn keys(&self) -> &[KeyEntry] {
+        &self.keys
+    }
+
+    pub fn binding_mode(&self) -> BindingMode {
+        self.binding_mode
+    }
+
+    // indices may point at different bindings afterwards, so nothing is held anymore
+    pub fn set_bindings(
+        &mut self,
+        keys: Vec<KeyEntry>,
+        controls: Vec<(Control, KeyBind)>,
+        debounce_ms: u64,
+    ) {
+        self.keys = keys;
+        self.controls = controls;
+        self.debounce_ms = debounce_ms;
+
+        self.held_keys.clear();
+        self.held_control = None;
+        self.last_release.clear();
+        self.debounced.clear();
+        self.catch_all_held.clear();
+
+        self.rebuild_lookup();
+    }
+
+    pub fn catch_all(&self) -> bool {
+        self.catch_all
+    }
+
+    pub fn set_catch_all(&mut self, catch_all: bool) {
+        self.catch_all = catch_all;
+        self.catch_all_held.clear();
+    }
+
+    pub fn set_binding_mode(&mut self, binding_mode: BindingMode) {
+        self.binding_mode = binding_mode;
+        self.rebuild_lookup();
+    }
+
+    fn lookup_key(&self, bind_input: &Input) -> LookupKey {
+        match (self.binding_mode, bind_input) {
+            (BindingMode::Scancode, Input::Key(key)) => match helper::key_to_scancode(key) {
+                Some(scancode) => LookupKey::Scancode(scancode),
+                None => LookupKey::Input(bind_input.clone()), // unknown codes have no position
+            },
+
+            _ => LookupKey::Input(bind_input.clone()),
+        }
+    }
+
+    // every key a bind could have been stored under for this event
+    fn event_keys(&self, input: &Input, scancode: Option<u32>) -> Vec<LookupKey> {
+        match (self.binding_mode, input, scancode) {
+            (BindingMode::Scancode, Input::Key(_), Some(scancode)) => vec![
+                LookupKey::Scancode(scancode),
+                LookupKey::Input(input.clone()),
+            ],
+
+            _ => vec![LookupKey::Input(input.clone())],
+        }
+    }
+
+    fn rebuild_lookup(&mut self) {
+        let mut lookup: HashMap<LookupKey, Vec<usize>> = HashMap::new();
+
+        for (i, entry) in self.keys.iter().enumerate() {
+            if let Some(bind_input) = entry.bind.input() {
+                lookup
+                    .entry(self.lookup_key(&bind_input))
+                    .or_default()
+                    .push(i);
+            }
+        }
+
+        self.lookup = lookup;
+    }
+
+    fn candidates(&self, input: &Input, scancode: Option<u32>) -> Vec<usize> {
+        let mut candidates: Vec<usize> = self
+            .event_keys(input, scancode)
+            .iter()
+            .filter_map(|key| self.lookup.get(key))
+            .flatten()
+            .copied()
+            .collect();
+
+        candidates.sort();
+        candidates
+    }
+
+    fn input_matches(&self, bind_input: &Input, input: &Input, scancode: Option<u32>) -> bool {
+        self.event_keys(input, scancode)
+            .contains(&self.lookup_key(bind_input))
+    }
+
+    // modifiers held alongside the input, then takes the input into account if it's a modifier
+    // a modifier key doesn't count as its own modifier
+    pub fn track_modifiers(&mut self, input: &Input, is_down: bool) -> Vec<Modifier> {
+        let mut modifiers: Vec<Modifier> = self
+            .held_modifiers
+            .iter()
+            .filter(|k| Input::Key(**k) != *input)
+            .filter_map(|k| k.as_modifier())
+            .collect();
+
+        modifiers.sort();
+        modifiers.dedup();
+
+        if let Input::Key(key) = input {
+            if key.as_modifier().is_some() {
+                self.held_modifiers.retain(|k| k != key);
+
+                if is_down {
+                    self.held_modifiers.push(*key);
+                }
+            }
+        }
+
+        modifiers
+    }
+
+    pub fn retain_modifiers(&mut self, keep: impl FnMut(&Key) -> bool) {
+        self.held_modifiers.retain(keep);
+    }
+
+    // a press this soon after the binding was let go is the switch bouncing, not a tap
+    fn is_chatter(&self, i: usize, time: &Timestamp, debounce_ms: u64) -> bool {
+        match self.last_release.get(&i) {
+            Some(released_at) => {
+                time.monotonic_ns.saturating_sub(*released_at) < debounce_ms * 1_000_000
+            }
+            None => false,
+        }
+    }
+
+    // applies an event, modifiers are the ones track_modifiers gave for it
+    pub fn handle(&mut self, event: &InputEvent, modifiers: &[Modifier]) -> Outcome {
+        let InputEvent {
+            input,
+            scancode,
+            is_down,
+            time,
+            device,
+        } = event;
+
+        for (control, bind) in &self.controls {
+            let control_key = match bind.key {
+                Some(control_key) => control_key,
+                None => continue,
+            };
+
+            if !self.input_matches(&Input::Key(control_key), input, *scancode) {
+                continue;
+            }
+
+            if *is_down && input::modifiers_match(&bind.modifiers, modifiers) {
+                self.held_control = Some(*control);
+                return Outcome::Held;
+            }
+
+            if !*is_down && self.held_control == Some(*control) {
+                self.held_control = None;
+                return Outcome::Control(*control);
+            }
+        }
+
+        let mut transitions = vec![];
+        let mut filtered = vec![];
+
+        let candidates = self.candidates(input, *scancode);
+
+        let catch_all = match (self.catch_all && candidates.is_empty(), input) {
+            (true, Input::Key(key)) => self.track_catch_all(key, *is_down, time, modifiers),
+            _ => None,
+        };
+
+        for i in candidates {
+            let entry = &self.keys[i];
+
+            // checked on release too, the same key on another device shouldn't let go of this one
+            if !entry.accepts(device.as_ref()) {
+                continue;
+            }
+
+            if *is_down {
+                // repeats of a held binding aren't presses, nor are repeats of a filtered one
+                if self.held_keys.contains_key(&i)
+                    || self.debounced.contains(&i)
+                    || !entry.bind.matches_modifiers(modifiers)
+                {
+                    continue;
+                }
+
+                // wheel notches come as instant press/release pairs, none of them bounce
+                let debounce_ms = match input {
+                    Input::Wheel(_) => 0,
+                    _ => entry.debounce_ms.unwrap_or(self.debounce_ms),
+                };
+
+                if self.is_chatter(i, time, debounce_ms) {
+                    self.debounced.insert(i);
+                    filtered.push(i);
+                    continue;
+                }
+
+                self.held_keys.insert(i, *time);
+
+                transitions.push(Transition {
+                    index: i,
+                    is_down: true,
+                    hold_ns: None,
+                });
+            } else {
+                if self.debounced.remove(&i) {
+                    continue;
+                }
+
+                // releases skip the modifier check, the modifiers may have been let go first
+                let pressed_at = match self.held_keys.remove(&i) {
+                    Some(pressed_at) => pressed_at,
+                    None => continue,
+                };
+
+                self.last_release.insert(i, time.monotonic_ns);
+
+                transitions.push(Transition {
+                    index: i,
+                    is_down: false,
+                    hold_ns: Some(time.monotonic_ns.saturating_sub(pressed_at.monotonic_ns)),
+                });
+            }
+        }
+
+        Outcome::Bindings {
+            transitions,
+            filtered,
+            catch_all,
+        }
+    }
+
+    // modifiers only ever show as part of a combo
+    fn track_catch_all(
+        &mut self,
+        key: &Key,
+        is_down: bool,
+        time: &Timestamp,
+        modifiers: &[Modifier],
+    ) -> Option<(Transition, String)> {
+        if key.as_modifier().is_some() {
+            return None;
+        }
+
+        let index = self.keys.len();
+
+        if is_down {
+            if self.catch_all_held.contains_key(key) {
+                return None;
+            }
+
+            let label = combo::combo(key, modifiers);
+            self.catch_all_held.insert(*key, (*time, label.clone()));
+
+            let transition = Transition {
+                index,
+                is_down: true,
+                hold_ns: None,
+            };
+
+            return Some((transition, label));
+        }
+
+        let (pressed_at, label) = self.catch_all_held.remove(key)?;
+
+        let transition = Transition {
+            index,
+            is_down: false,
+            hold_ns: Some(time.monotonic_ns.saturating_sub(pressed_at.monotonic_ns)),
+        };
+
+        Some((transition, label))
+    }
+
+    // held bindings and when they were pressed, in binding order
+    pub fn held(&self) -> Vec<(usize, Timestamp)> {
+        let mut held: Vec<(usize, Timestamp)> = self
+            .held_keys
+            .iter()
+            .map(|(i, pressed_at)| (*i, *pressed_at))
+            .collect();
+
+        held.sort_by_key(|(i, _)| *i);
+        held
+    }
+
+    // lets go of every unbound key in the catch-all slot
+    pub fn release_catch_all(&mut self, time: &Timestamp) -> Vec<(Transition, String)> {
+        let index = self.keys.len();
+
+        self.catch_all_held
+            .drain()
+            .map(|(_, (pressed_at, label))| {
+                let transition = Transition {
+                    index,
+                    is_down: false,
+                    hold_ns: Some(time.monotonic_ns.saturating_sub(pressed_at.monotonic_ns)),
+                };
+
+                (transition, label)
+            })
+            .collect()
+    }
+
+    // lets go of a binding whose real release never arrived, None if it isn't held anymore
+    pub fn force_release(&mut self, i: usize, time: &Timestamp) -> Option<Transition> {
+        let pressed_at = self.held_keys.remove(&i)?;
+
+        Some(Transition {
+            index: i,
+            is_down: false,
+            hold_ns: Some(time.monotonic_ns.saturating_sub(pressed_at.monotonic_ns)),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::SystemTime;
+
+    use super::*;
+    use crate::input::{Bind, MouseButton, WheelDirection};
+
+    fn at(ms: u64) -> Timestamp {
+        Timestamp {
+            system: SystemTime::UNIX_EPOCH,
+            monotonic_ns: ms * 1_000_000,
+        }
+    }
+
+    fn key(key: Key) -> KeyEntry {
+        KeyEntry::new(Bind::Key(KeyBind::new(Some(key), vec![])), None, None)
+    }
+
+    fn state(keys: Vec<KeyEntry>, controls: Vec<(Control, KeyBind)>) -> KeyboardState {
+        let mut state = KeyboardState::new();
+        state.set_bindings(keys, controls, 0);
+        state
+    }
+
+    fn send(state: &mut KeyboardState, input: Input, is_down: bool, ms: u64) -> Outcome {
+        let scancode = match &input {
+            Input::Key(key) => helper::key_to_scancode(key),
+            _ => None,
+        };
+
+        let event = InputEvent::new(input.clone(), scancode, is_down, at(ms));
+        let modifiers = state.track_modifiers(&input, is_down);
+
+        state.handle(&event, &modifiers)
+    }
+
+    fn transitions(outcome: Outcome) -> Vec<(usize, bool, Option<u64>)> {
+        match outcome {
+            Outcome::Bindings { transitions, .. } => transitions
+                .into_iter()
+                .map(|t| (t.index, t.is_down, t.hold_ns))
+                .collect(),
+            outcome => panic!("expected bindings, got {:?}", outcome),
+        }
+    }
+
+    #[test]
+    fn press_and_release() {
+        let mut state = state(vec![key(Key::Z), key(Key::X)], vec![]);
+
+        let pressed = send(&mut state, Input::Key(Key::Z), true, 10);
+        assert_eq!(transitions(pressed), vec![(0, true, None)]);
+        assert_eq!(state.held().len(), 1);
+
+        let released = send(&mut state, Input::Key(Key::Z), false, 35);
+        assert_eq!(transitions(released), vec![(0, false, Some(25_000_000))]);
+        assert!(state.held().is_empty());
+    }
+
+    #[test]
+    fn repeats_are_not_presses() {
+        let mut state = state(vec![key(Key::Z)], vec![]);
+
+        send(&mut state, Input::Key(Key::Z), true, 0);
+
+        for ms in [30, 60, 90] {
+            assert!(transitions(send(&mut state, Input::Key(Key::Z), true, ms)).is_empty());
+        }
+
+        let released = send(&mut state, Input::Key(Key::Z), false, 100);
+        assert_eq!(transitions(released), vec![(0, false, Some(100_000_000))]);
+    }
+
+    #[test]
+    fn release_without_press_is_ignored() {
+        let mut state = state(vec![key(Key::Z)], vec![]);
+
+        assert!(transitions(send(&mut state, Input::Key(Key::Z), false, 0)).is_empty());
+    }
+
+    #[test]
+    fn unbound_input_is_ignored() {
+        let mut state = state(vec![key(Key::Z)], vec![]);
+
+        assert!(transitions(send(&mut state, Input::Key(Key::X), true, 0)).is_empty());
+        assert!(transitions(send(&mut state, Input::Mouse(MouseButton::Left), true, 0)).is_empty());
+    }
+
+    #[test]
+    fn shared_input_triggers_every_binding() {
+        let mut state = state(vec![key(Key::Z), key(Key::X), key(Key::Z)], vec![]);
+
+        let pressed = send(&mut state, Input::Key(Key::Z), true, 0);
+        assert_eq!(transitions(pressed), vec![(0, true, None), (2, true, None)]);
+    }
+
+    #[test]
+    fn modifiers_must_match_on_press_only() {
+        let shifted = KeyEntry::new(
+            Bind::Key(KeyBind::new(Some(Key::Z), vec![Modifier::Shift])),
+            None,
+            None,
+        );
+        let mut state = state(vec![shifted], vec![]);
+
+        assert!(transitions(send(&mut state, Input::Key(Key::Z), true, 0)).is_empty());
+        send(&mut state, Input::Key(Key::Z), false, 5);
+
+        send(&mut state, Input::Key(Key::LShift), true, 10);
+        let pressed = send(&mut state, Input::Key(Key::Z), true, 20);
+        assert_eq!(transitions(pressed), vec![(0, true, None)]);
+
+        // shift let go first, the release still counts
+        send(&mut state, Input::Key(Key::LShift), false, 30);
+        let released = send(&mut state, Input::Key(Key::Z), false, 40);
+        assert_eq!(transitions(released), vec![(0, false, Some(20_000_000))]);
+    }
+
+    #[test]
+    fn windows_key_is_not_control() {
+        let controlled = KeyEntry::new(
+            Bind::Key(KeyBind::new(Some(Key::L), vec![Modifier::Control])),
+            None,
+            None,
+        );
+        let mut state = state(vec![controlled], vec![]);
+
+        send(&mut state, Input::Key(Key::LWindows), true, 0);
+        assert!(transitions(send(&mut state, Input::Key(Key::L), true, 10)).is_empty());
+        send(&mut state, Input::Key(Key::L), false, 20);
+        send(&mut state, Input::Key(Key::LWindows), false, 30);
+
+        send(&mut state, Input::Key(Key::RControl), true, 40);
+        let pressed = send(&mut state, Input::Key(Key::L), true, 50);
+        assert_eq!(transitions(pressed), vec![(0, true, None)]);
+    }
+
+    #[test]
+    fn plain_binds_ignore_held_modifiers() {
+        let mut state = state(vec![key(Key::Z)], vec![]);
+
+        send(&mut state, Input::Key(Key::LShift), true, 0);
+        let pressed = send(&mut state, Input::Key(Key::Z), true, 10);
+        assert_eq!(transitions(pressed), vec![(0, true, None)]);
+
+        let released = send(&mut state, Input::Key(Key::Z), false, 20);
+        assert_eq!(transitions(released), vec![(0, false, Some(10_000_000))]);
+    }
+
+    #[test]
+    fn reset_runs_on_release() {
+        let reset = KeyBind::new(Some(Key::Escape), vec![]);
+        let mut state = state(vec![key(Key::Z)], vec![(Control::Reset, reset)]);
+
+        assert_eq!(
+            send(&mut state, Input::Key(Key::Escape), true, 0),
+            Outcome::Held
+        );
+        assert_eq!(
+            send(&mut state, Input::Key(Key::Escape), false, 10),
+            Outcome::Control(Control::Reset)
+        );
+
+        // a stray release doesn't run it again
+        assert!(transitions(send(&mut state, Input::Key(Key::Escape), false, 20)).is_empty());
+    }
+
+    #[test]
+    fn bindings_keep_working_around_reset() {
+        let reset = KeyBind::new(Some(Key::Escape), vec![]);
+        let mut state = state(vec![key(Key::Z)], vec![(Control::Reset, reset)]);
+
+        send(&mut state, Input::Key(Key::Z), true, 0);
+        send(&mut state, Input::Key(Key::Escape), true, 5);
+        send(&mut state, Input::Key(Key::Escape), false, 10);
+
+        let released = send(&mut state, Input::Key(Key::Z), false, 15);
+        assert_eq!(transitions(released), vec![(0, false, Some(15_000_000))]);
+    }
+
+    #[test]
+    fn chatter_is_filtered_with_its_release() {
+        let mut state = KeyboardState::new();
+        state.set_bindings(vec![key(Key::Z)], vec![], 20);
+
+        send(&mut state, Input::Key(Key::Z), true, 0);
+        send(&mut state, Input::Key(Key::Z), false, 50);
+
+        match send(&mut state, Input::Key(Key::Z), true, 55) {
+            Outcome::Bindings {
+                transitions,
+                filtered,
+                ..
+            } => {
+                assert!(transitions.is_empty());
+                assert_eq!(filtered, vec![0]);
+            }
+            outcome => panic!("expected bindings, got {:?}", outcome),
+        }
+
+        assert!(transitions(send(&mut state, Input::Key(Key::Z), false, 60)).is_empty());
+
+        let pressed = send(&mut state, Input::Key(Key::Z), true, 100);
+        assert_eq!(transitions(pressed), vec![(0, true, None)]);
+    }
+
+    #[test]
+    fn repeats_of_chatter_are_not_presses() {
+        let mut state = KeyboardState::new();
+        state.set_bindings(vec![key(Key::Z)], vec![], 20);
+
+        send(&mut state, Input::Key(Key::Z), true, 0);
+        send(&mut state, Input::Key(Key::Z), false, 50);
+        send(&mut state, Input::Key(Key::Z), true, 55);
+
+        // autorepeat, long after the debounce window
+        assert!(transitions(send(&mut state, Input::Key(Key::Z), true, 600)).is_empty());
+        assert!(transitions(send(&mut state, Input::Key(Key::Z), false, 650)).is_empty());
+
+        assert!(state.held().is_empty());
+    }
+
+    #[test]
+    fn wheel_notches_are_not_chatter() {
+        let wheel = KeyEntry::new(Bind::Wheel(WheelDirection::Down), None, None);
+
+        let mut state = KeyboardState::new();
+        state.set_bindings(vec![wheel], vec![], 20);
+
+        for ms in [0, 5, 10] {
+            let pressed = send(&mut state, Input::Wheel(WheelDirection::Down), true, ms);
+            assert_eq!(transitions(pressed), vec![(0, true, None)]);
+
+            let released = send(&mut state, Input::Wheel(WheelDirection::Down), false, ms);
+            assert_eq!(transitions(released), vec![(0, false, Some(0))]);
+        }
+    }
+
+    #[test]
+    fn force_release_happens_once() {
+        let mut state = state(vec![key(Key::Z)], vec![]);
+
+        send(&mut state, Input::Key(Key::Z), true, 0);
+
+        let released = state.force_release(0, &at(40));
+        assert_eq!(
+            released.map(|t| (t.index, t.is_down, t.hold_ns)),
+            Some((0, false, Some(40_000_000)))
+        );
+        assert_eq!(state.force_release(0, &at(50)), None);
+
+        // the real release arriving late is ignored
+        assert!(transitions(send(&mut state, Input::Key(Key::Z), false, 60)).is_empty());
+    }
+
+    #[test]
+    fn new_bindings_drop_held_state() {
+        let mut state = state(vec![key(Key::Z)], vec![]);
+
+        send(&mut state, Input::Key(Key::Z), true, 0);
+        state.set_bindings(vec![key(Key::Z)], vec![], 0);
+
+        assert!(state.held().is_empty());
+        assert!(transitions(send(&mut state, Input::Key(Key::Z), false, 10)).is_empty());
+    }
+
+    #[test]
+    fn catch_all_takes_unbound_keys() {
+        let mut state = state(vec![key(Key::Z)], vec![]);
+        state.set_catch_all(true);
+
+        send(&mut state, Input::Key(Key::LControl), true, 0);
+
+        match send(&mut state, Input::Key(Key::P), true, 10) {
+            Outcome::Bindings { catch_all, .. } => {
+                let (transition, label) = catch_all.unwrap();
+                assert_eq!((transition.index, transition.is_down), (1, true));
+                assert_eq!(label, "Ctrl+P");
+            }
+            outcome => panic!("expected bindings, got {:?}", outcome),
+        }
+
+        send(&mut state, Input::Key(Key::LControl), false, 20);
+
+        match send(&mut state, Input::Key(Key::P), false, 30) {
+            Outcome::Bindings { catch_all, .. } => {
+                let (transition, label) = catch_all.unwrap();
+                assert_eq!(transition.hold_ns, Some(20_000_000));
+                assert_eq!(label, "Ctrl+P");
+            }
+            outcome => panic!("expected bindings, got {:?}", outcome),
+        }
+
+        // bound keys stay out of it
+        match send(&mut state, Input::Key(Key::Z), true, 40) {
+            Outcome::Bindings { catch_all, .. } => assert!(catch_all.is_none()),
+            outcome => panic!("expected bindings, got {:?}", outcome),
+        }
+    }
+
+    #[test]
+    fn scancode_mode_matches_by_position() {
+        let mut state = state(vec![key(Key::Z)], vec![]);
+        state.set_binding_mode(BindingMode::Scancode);
+
+        let z_scancode = helper::key_to_scancode(&Key::Z);
+        let event = InputEvent::new(Input::Key(Key::Y), z_scancode, true, at(0));
+
+        let outcome = state.handle(&event, &[]);
+        assert_eq!(transitions(outcome), vec![(0, true, None)]);
+    }
+}
@@ -0,0 +1,195 @@
+// the window input is going to, for pausing around sensitive apps
+pub struct FocusedWindow {
+    pub title: String,
+    pub class: String,
+}
+
+impl FocusedWindow {
+    // rules are case-insensitive substrings of the title or class
+    pub fn matches(&self, rule: &str) -> bool {
+        let rule = rule.trim().to_lowercase();
+
+        !rule.is_empty()
+            && (self.title.to_lowercase().contains(&rule)
+                || self.class.to_lowercase().contains(&rule))
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod x11_focus {
+    use std::os::raw::{c_int, c_ulong};
+    use std::ptr;
+    use std::sync::atomic::{AtomicPtr, Ordering};
+    use std::sync::OnceLock;
+
+    use x11::xlib;
+
+    type ErrorHandler = unsafe extern "C" fn(*mut xlib::Display, *mut xlib::XErrorEvent) -> c_int;
+
+    // opened on the first check and kept, focus is only checked from one thread
+    static DISPLAY: AtomicPtr<xlib::Display> = AtomicPtr::new(ptr::null_mut());
+    static PREVIOUS_HANDLER: OnceLock<Option<ErrorHandler>> = OnceLock::new();
+
+    // a window can close while it's being looked at, the default handler would exit over that.
+    // errors on other connections go wherever they went before
+    unsafe extern "C" fn on_error(
+        display: *mut xlib::Display,
+        event: *mut xlib::XErrorEvent,
+    ) -> c_int {
+        if display == DISPLAY.load(Ordering::Relaxed) {
+            return 0;
+        }
+
+        match PREVIOUS_HANDLER.get().copied().flatten() {
+            Some(previous) => previous(display, event),
+            None => 0,
+        }
+    }
+
+    pub fn display() -> anyhow::Result<*mut xlib::Display> {
+        let display = DISPLAY.load(Ordering::Relaxed);
+
+        if !display.is_null() {
+            return Ok(display);
+        }
+
+        // safe, a null name opens whatever DISPLAY says
+        let display = unsafe { xlib::XOpenDisplay(ptr::null()) };
+
+        if display.is_null() {
+            anyhow::bail!("Failed to connect to the X server");
+        }
+
+        DISPLAY.store(display, Ordering::Relaxed);
+        PREVIOUS_HANDLER.get_or_init(|| unsafe { xlib::XSetErrorHandler(Some(on_error)) });
+
+        Ok(display)
+    }
+
+    // T is u8 for 8 bit properties and c_ulong for 32 bit ones, which xlib hands out as longs
+    // safe as long as the display is open
+    pub unsafe fn property<T: Copy>(
+        display: *mut xlib::Display,
+        window: xlib::Window,
+        name: &str,
+    ) -> Option<Vec<T>> {
+        let name = std::ffi::CString::new(name).ok()?;
+        let atom = xlib::XInternAtom(display, name.as_ptr(), xlib::False);
+
+        let mut actual_type: xlib::Atom = 0;
+        let mut actual_format: c_int = 0;
+        let mut items: c_ulong = 0;
+        let mut bytes_after: c_ulong = 0;
+        let mut data: *mut u8 = ptr::null_mut();
+
+        let status = xlib::XGetWindowProperty(
+            display,
+            window,
+            atom,
+            0,
+            1024,
+            xlib::False,
+            xlib::AnyPropertyType as c_ulong,
+            &mut actual_type,
+            &mut actual_format,
+            &mut items,
+            &mut bytes_after,
+            &mut data,
+        );
+
+        if status != xlib::Success as c_int || data.is_null() {
+            return None;
+        }
+
+        let expected_format = match std::mem::size_of::<T>() {
+            1 => 8,
+            _ => 32,
+        };
+
+        let values = match actual_format == expected_format {
+            true => std::slice::from_raw_parts(data as *const T, items as usize).to_vec(),
+            false => vec![],
+        };
+
+        xlib::XFree(data as *mut _);
+
+        Some(values)
+    }
+}
+
+// x11 only, errors mean there's no telling which window has focus, None that no window has it
+#[cfg(target_os = "linux")]
+pub fn focused() -> anyhow::Result<Option<FocusedWindow>> {
+    use anyhow::Context;
+    use std::os::raw::c_ulong;
+    use x11::xlib;
+
+    // xwayland only knows about its own windows
+    if std::env::var("XDG_SESSION_TYPE").is_ok_and(|session| session == "wayland") {
+        anyhow::bail!("The focused window can't be read on Wayland");
+    }
+
+    let display = x11_focus::display()?;
+
+    // safe, the display stays open
+    unsafe {
+        let root = xlib::XDefaultRootWindow(display);
+
+        let active = x11_focus::property::<c_ulong>(display, root, "_NET_ACTIVE_WINDOW")
+            .with_context(|| "The window manager doesn't say which window has focus")?;
+
+        let id = match active.first() {
+            Some(&id) if id != 0 => id,
+            _ => return Ok(None),
+        };
+
+        let text = |name| {
+            let bytes = x11_focus::property::<u8>(display, id, name).unwrap_or_default();
+            String::from_utf8_lossy(&bytes).to_string()
+        };
+
+        // WM_CLASS has the instance then the class, each ending in a nul
+        let class = text("WM_CLASS")
+            .split('\0')
+            .filter(|part| !part.is_empty())
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        Ok(Some(FocusedWindow {
+            title: text("_NET_WM_NAME"),
+            class,
+        }))
+    }
+}
+
+#[cfg(target_os = "windows")]
+pub fn focused() -> anyhow::Result<Option<FocusedWindow>> {
+    use winapi::um::winuser::{GetClassNameW, GetForegroundWindow, GetWindowTextW};
+
+    let mut title = [0_u16; 512];
+    let mut class = [0_u16; 256];
+
+    // safe as long as the lengths passed are the buffer lengths
+    let (title_len, class_len) = unsafe {
+        let window = GetForegroundWindow();
+
+        if window.is_null() {
+            return Ok(None);
+        }
+
+        (
+            GetWindowTextW(window, title.as_mut_ptr(), title.len() as i32),
+            GetClassNameW(window, class.as_mut_ptr(), class.len() as i32),
+        )
+    };
+
+    Ok(Some(FocusedWindow {
+        title: String::from_utf16_lossy(&title[..title_len.max(0) as usize]),
+        class: String::from_utf16_lossy(&class[..class_len.max(0) as usize]),
+    }))
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "windows")))]
+pub fn focused() -> anyhow::Result<Option<FocusedWindow>> {
+    anyhow::bail!("The focused window can't be read on this platform");
+}